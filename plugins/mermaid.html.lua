@@ -1,8 +1,16 @@
-function transform(text)
+local themes = { default = true, neutral = true, dark = true, forest = true, base = true }
+
+function transform(text, args)
   -- [note] since there's no way to add this script though mdBook without downloading the file
   -- we can just import mermaid via CDN
-  importMermaid = '<script type="module"> import mermaid from "https://cdn.jsdelivr.net/npm/mermaid@10.0.2/+esm"; mermaid.initialize({}); </script>'
+  local theme = args.theme or config.theme or 'default'
 
-  result = '<pre class="mermaid">' .. text .. '</pre>' .. importMermaid
-end
+  -- the theme goes into a script, so only known themes are allowed
+  if not themes[theme] then
+    error('"' .. tostring(theme) .. '" isn\'t a valid Mermaid theme', 0)
+  end
 
+  importMermaid = '<script type="module"> import mermaid from "https://cdn.jsdelivr.net/npm/mermaid@10.0.2/+esm"; mermaid.initialize({ theme: "' .. theme .. '" }); </script>'
+
+  return '<pre class="mermaid">' .. text .. '</pre>' .. importMermaid
+end
@@ -0,0 +1,4 @@
+function transform(text)
+  -- GitHub, GitLab and many other markdown viewers render mermaid code blocks as diagrams
+  return '```mermaid\n' .. text .. '\n```'
+end
@@ -7,6 +7,31 @@ pub fn create_cli() -> ArgMatches {
         .about("\nFundoc extracts documentation from source files and merge it into readable .md files with references to the sources")
         .arg(arg!(-i --init "Creates the config file"))
         .arg(arg!(-e --extension "This flag is only for running Fundoc as an extension for mdBook. It requires by mdBook preprocessors API"))
+        .arg(arg!(--name <NAME> "A name of the preprocessor in book.toml if several preprocessors run Fundoc"))
         .arg(arg!([supports] ... "Check if fundoc has a plugin for passed file type from mdBook"))
+        .subcommand(
+            Command::new("diff")
+                .about("Shows how documentation has changed between two git references")
+                .arg(arg!(<base> "A base git reference"))
+                .arg(arg!(<head> "A head git reference"))
+                .arg(arg!(-o --output <FILE> "A file to write the report to instead of stdout")),
+        )
+        .subcommand(
+            Command::new("stale")
+                .about("Shows documentation sections which code was changed after the documentation"),
+        )
+        .subcommand(
+            Command::new("plugin")
+                .about("Helps to develop plugins")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("new")
+                        .about("Creates a plugin from a template with an example fixture")
+                        .arg(arg!(<name> "A name of the plugin")),
+                )
+                .subcommand(
+                    Command::new("test").about("Runs plugins against their fixtures and shows diffs"),
+                ),
+        )
         .get_matches()
 }
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::num::NonZeroUsize;
+use std::process;
 
 use dialoguer::console::Style;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input};
@@ -35,7 +38,7 @@ pub struct Config {
     /**
      * @Article Configuration
      * - `repositories` - you can specify a repository list by setting to parse and generate documentation from all
-     * repositories from the list. It can be useful if you have many repositories.
+     *   repositories from the list. It can be useful if you have many repositories.
      *
      * For example a microservices architecture:
      *
@@ -47,16 +50,84 @@ pub struct Config {
      * }}
      */
     pub repositories: Option<Vec<String>>,
+    /**
+     * @Article Configuration
+     *
+     * - `clone_concurrency` - how many repositories from the `repositories` list can be cloned at the
+     *   same time. By default, it's `4`.
+     * - `fail_on_repository_error` - if true Fundoc stops documentation generation when any of the
+     *   repositories cannot be cloned. Otherwise broken repositories are skipped and reported.
+     */
+    pub clone_concurrency: Option<usize>,
+    pub fail_on_repository_error: Option<bool>,
     /**
      * @Article Configuration
      *
      * - `repository_host` - an http url which will be used for creating a link to a file in a
-     * repository. For example, if you want to add links to your files for each section you can pass
-     * a value like `https://github.com/user_name/project_name/blob/master`. It will be used for
-     * creating an url like this
-     * `https://github.com/user_name/project_name/blob/master/path/to/your/file.txt`.
+     *   repository. For example, if you want to add links to your files for each section you can pass
+     *   a value like `https://github.com/user_name/project_name/blob/master`. It will be used for
+     *   creating an url like this
+     *   `https://github.com/user_name/project_name/blob/master/path/to/your/file.txt`.
      */
     pub repository_host: Option<String>,
+    /**
+     * @Article Configuration
+     *
+     * - `permalinks` - if true links to source files point to the current commit of a project
+     *   instead of a branch, so they don't break when lines move. Each repository from the
+     *   `repositories` list uses its own `repository_host` and commit.
+     */
+    pub permalinks: Option<bool>,
+    /**
+     * @Article Configuration
+     *
+     * - `source_link_template` - a template of links to source files. It can be a name of a built-in
+     *   preset or a custom template with the following placeholders: `{host}` (`repository_host`
+     *   without a trailing slash), `{path}`, `{start}`, `{end}` (the first and the last lines of a
+     *   section) and `{ref}` (the current branch or the current commit if `permalinks` is enabled).
+     *
+     * Built-in presets:
+     *
+     * - `github` - `{host}/{path}#L{start}-L{end}`
+     * - `gitlab` - `{host}/{path}#L{start}-{end}`
+     * - `bitbucket` - `{host}/{path}#lines-{start}:{end}`
+     * - `gitea` - `{host}/{path}#L{start}-L{end}`
+     * - `azure` - `{host}?path=/{path}&version=GB{ref}&line={start}&lineEnd={end}&lineStartColumn=1&lineEndColumn=1`
+     *
+     * If the template isn't specified, a preset is detected from the domain of `repository_host` or
+     * of the `origin` git remote, and urls with `/-/blob/` are treated as GitLab ones. GitHub's links
+     * are used by default. A value which is neither a preset nor
+     * a template with `{path}` stops the generation with an error.
+     */
+    pub source_link_template: Option<String>,
+    /**
+     * @Article Configuration
+     *
+     * - `versions` - a list of git references (tags or branches) of the project which documentation
+     *   should be generated for. Unix style patterns are supported, so `["v*", "main"]` means all tags
+     *   starting with `v` and the `main` branch. Documentation of each version is placed into
+     *   `docs_folder/<version>/` and all versions are listed on a version index page.
+     *
+     * > **NOTE** documentation from `repositories` isn't generated in this mode.
+     */
+    pub versions: Option<Vec<String>>,
+    /**
+     * @Article Configuration
+     *
+     * - `git_metadata` - if true Fundoc takes the date, the author and the commit of the last change
+     *   of each section from the git history and adds a "Last updated" footer to every section and
+     *   article.
+     */
+    pub git_metadata: Option<bool>,
+    /**
+     * @Article Configuration
+     *
+     * - `stale_code_lines` and `stale_threshold_days` - parameters of the `fundoc stale` command.
+     *   Read more about it in the [Stale documentation](./stale_documentation.md) article.
+     *   `stale_code_lines` must be a positive number.
+     */
+    pub stale_code_lines: Option<NonZeroUsize>,
+    pub stale_threshold_days: Option<i64>,
     /**
      * @Article Configuration
      *
@@ -83,6 +154,44 @@ pub struct Config {
      * `plugins_dir` - path to the plugins directory.
      */
     pub plugins_dir: Option<String>,
+    /**
+     * @Article Configuration
+     *
+     * `plugins` - options of plugins. Keys are names of plugins and values are passed to the plugins
+     * as the `config` table. For example, `{ "mermaid": { "theme": "dark" } }`.
+     */
+    pub plugins: Option<HashMap<String, serde_json::Value>>,
+    /**
+     * @Article Configuration
+     *
+     * `plugins_fs_write` - if true plugins can write files with `fs.write`.
+     * `plugins_fs_outside_root` - if true plugins can access files outside the project root.
+     */
+    pub plugins_fs_write: Option<bool>,
+    pub plugins_fs_outside_root: Option<bool>,
+    /**
+     * @Article Configuration
+     *
+     * `plugins_instruction_limit` - how many Lua instructions a plugin can execute for one block. By
+     * default, it's `100000000`.
+     * `plugins_memory_limit_mb` - how much memory all Lua plugins can use. By default, it's `64`.
+     */
+    pub plugins_instruction_limit: Option<u64>,
+    pub plugins_memory_limit_mb: Option<usize>,
+    /**
+     * @Article Configuration
+     *
+     * `native_plugins` - if true native plugins (`.so`, `.dylib` and `.dll` libraries) from the
+     * plugins folder are loaded. They aren't sandboxed, so they are disabled by default.
+     */
+    pub native_plugins: Option<bool>,
+    /**
+     * @Article Configuration
+     *
+     * `diagrams_cache_dir` - a directory for rendered Graphviz and PlantUML diagrams. By default,
+     * it's `./.fundoc/diagrams`.
+     */
+    pub diagrams_cache_dir: Option<String>,
 }
 
 /**
@@ -103,7 +212,10 @@ pub fn read_config(path: Option<&str>) -> Option<Config> {
                 println!("Cannot read config file");
             };
 
-            config = serde_json::from_str(&content).unwrap();
+            config = serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Invalid config file: {}", e);
+                process::exit(1);
+            });
         }
         Err(e) => {
             println!("{:?}", e);
@@ -176,15 +288,30 @@ pub fn create_default_config() -> Config {
         docs_folder,
         project_path,
         repository_host,
+        permalinks: None,
+        source_link_template: None,
+        versions: None,
+        git_metadata: None,
+        stale_code_lines: None,
+        stale_threshold_days: None,
         book_name,
         book_build_dir,
         mdbook: Some(mdbook),
         files_patterns: vec![String::from("**/*.rs")],
         repositories: None,
+        clone_concurrency: None,
+        fail_on_repository_error: None,
         comment_start_string: None,
         comment_end_string: None,
         comment_prefix: None,
         plugins_dir: Some(String::from("./plugins")),
+        plugins: None,
+        plugins_fs_write: None,
+        plugins_fs_outside_root: None,
+        plugins_instruction_limit: None,
+        plugins_memory_limit_mb: None,
+        native_plugins: None,
+        diagrams_cache_dir: None,
     };
 
     let config_str = serde_json::to_string_pretty(&config).unwrap();
@@ -0,0 +1,409 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config;
+
+const DEFAULT_CACHE_DIR: &str = "./.fundoc/diagrams";
+const MERMAID_URL: &str = "https://cdn.jsdelivr.net/npm/mermaid@10.0.2/+esm";
+const DEFAULT_PLANTUML_JAR: &str = "plantuml.jar";
+const MERMAID_THEMES: [&str; 5] = ["default", "neutral", "dark", "forest", "base"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagram {
+    Mermaid,
+    Graphviz,
+    PlantUml,
+}
+
+pub const DIAGRAMS: [Diagram; 3] = [Diagram::Mermaid, Diagram::Graphviz, Diagram::PlantUml];
+
+#[derive(Debug, Clone)]
+pub struct DiagramCache {
+    dir: PathBuf,
+}
+
+impl DiagramCache {
+    pub fn new(config: &config::Config) -> Self {
+        Self {
+            dir: PathBuf::from(
+                config
+                    .diagrams_cache_dir
+                    .as_deref()
+                    .unwrap_or(DEFAULT_CACHE_DIR),
+            ),
+        }
+    }
+
+    fn get_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.svg", key))
+    }
+
+    fn get_or_render(
+        &self,
+        key: &str,
+        render: impl FnOnce() -> Result<String, String>,
+    ) -> Result<String, String> {
+        if let Ok(content) = fs::read_to_string(self.get_path(key)) {
+            return Ok(content);
+        }
+
+        let content = render()?;
+        let saved =
+            fs::create_dir_all(&self.dir).and_then(|_| fs::write(self.get_path(key), &content));
+
+        if let Err(e) = saved {
+            eprintln!("Cannot cache a diagram in {}: {}", self.dir.display(), e);
+        }
+
+        Ok(content)
+    }
+}
+
+fn get_hash(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn get_option(
+    args: &HashMap<String, String>,
+    options: &serde_json::Value,
+    name: &str,
+) -> Option<String> {
+    args.get(name)
+        .cloned()
+        .or_else(|| get_config_option(options, name))
+}
+
+// commands and paths come only from the config, so a document can't run arbitrary programs
+fn get_config_option(options: &serde_json::Value, name: &str) -> Option<String> {
+    options[name].as_str().map(String::from)
+}
+
+fn run_command(program: &str, args: &[String], input: &str, tool: &str) -> Result<String, String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => format!(
+                "{} isn't installed: cannot find the `{}` command",
+                tool, program
+            ),
+            _ => format!("Cannot run `{}`: {}", program, e),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|e| format!("Cannot pass a diagram to `{}`: {}", program, e))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Cannot run `{}`: {}", program, e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+
+        return Err(format!("`{}` failed: {}", program, message.trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// markdown ends an HTML block on an empty line, so SVG images are inlined without them
+fn extract_svg(output: &str, tool: &str) -> Result<String, String> {
+    let start = output
+        .find("<svg")
+        .ok_or_else(|| format!("{} didn't return an SVG image", tool))?;
+
+    Ok(output[start..]
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn render_mermaid(
+    text: &str,
+    args: &HashMap<String, String>,
+    options: &serde_json::Value,
+    renderer: &str,
+) -> Result<String, String> {
+    if renderer != "html" {
+        return Ok(format!("```mermaid\n{}\n```", text));
+    }
+
+    let theme = get_option(args, options, "theme").unwrap_or_else(|| String::from("default"));
+
+    if !MERMAID_THEMES.contains(&theme.as_str()) {
+        return Err(format!(
+            "\"{}\" isn't a valid Mermaid theme: use one of {}",
+            theme,
+            MERMAID_THEMES.join(", ")
+        ));
+    }
+
+    Ok(format!(
+        "<pre class=\"mermaid\">{}</pre><script type=\"module\"> import mermaid from \"{}\"; mermaid.initialize({{ theme: \"{}\" }}); </script>",
+        text, MERMAID_URL, theme
+    ))
+}
+
+fn render_graphviz(
+    text: &str,
+    args: &HashMap<String, String>,
+    options: &serde_json::Value,
+    cache: &DiagramCache,
+) -> Result<String, String> {
+    let program = get_config_option(options, "command").unwrap_or_else(|| String::from("dot"));
+    let mut command_args = vec![String::from("-Tsvg")];
+
+    if let Some(engine) = get_option(args, options, "engine") {
+        if !engine.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("\"{}\" isn't a valid Graphviz engine", engine));
+        }
+        command_args.push(format!("-K{}", engine));
+    }
+
+    let key = get_hash(&["graphviz", &program, &command_args.join(" "), text]);
+
+    cache.get_or_render(&key, || {
+        extract_svg(
+            &run_command(&program, &command_args, text, "Graphviz")?,
+            "Graphviz",
+        )
+    })
+}
+
+fn render_plantuml(
+    text: &str,
+    options: &serde_json::Value,
+    cache: &DiagramCache,
+) -> Result<String, String> {
+    let text = if text.trim_start().starts_with("@start") {
+        text.to_string()
+    } else {
+        format!("@startuml\n{}\n@enduml", text)
+    };
+    let program = get_config_option(options, "java").unwrap_or_else(|| String::from("java"));
+    let jar = get_config_option(options, "jar")
+        .or_else(|| std::env::var("PLANTUML_JAR").ok())
+        .unwrap_or_else(|| String::from(DEFAULT_PLANTUML_JAR));
+    let key = get_hash(&["plantuml", &program, &jar, &text]);
+
+    cache.get_or_render(&key, || {
+        if !Path::new(&jar).exists() {
+            return Err(format!(
+                "Cannot find the PlantUML jar \"{}\": set the `jar` option of the plantuml plugin or the PLANTUML_JAR environment variable",
+                jar
+            ));
+        }
+
+        let command_args: Vec<String> = ["-jar", &jar, "-tsvg", "-pipe", "-charset", "UTF-8"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        extract_svg(
+            &run_command(&program, &command_args, &text, "Java")?,
+            "PlantUML",
+        )
+    })
+}
+
+/**
+ * @Article Plugins
+ *
+ * ## Diagrams
+ *
+ * Fundoc has built-in plugins for diagrams, so they work without any configuration:
+ *
+ * - `mermaid` - [Mermaid](https://mermaid.js.org) diagrams which are rendered in a browser. The
+ *   `theme` option sets a theme of diagrams: `default`, `neutral`, `dark`, `forest` or `base`. For
+ *   the `markdown` renderer a diagram becomes a `mermaid` code block.
+ * - `graphviz` - [Graphviz](https://graphviz.org) diagrams which are rendered into SVG by the `dot`
+ *   command. The `engine` option sets a layout engine (for example, `engine=neato`), and the
+ *   `command` option sets a path to the `dot` binary.
+ * - `plantuml` - [PlantUML](https://plantuml.com) diagrams which are rendered into SVG by the local
+ *   PlantUML jar. The `jar` option or the `PLANTUML_JAR` environment variable sets a path to the
+ *   jar, by default it's `plantuml.jar`. `@startuml` and `@enduml` can be omitted.
+ *
+ * `theme` and `engine` can be passed as arguments of a block or set in the config file like options
+ * of other plugins. `command`, `jar` and the `java` option with a path to Java are read only from
+ * the config file, so documents can't run other programs. If `dot` or the jar can't be found,
+ * Fundoc stops the build and explains what is missing.
+ *
+ * Rendered Graphviz and PlantUML diagrams are cached by a hash of their content in
+ * `diagrams_cache_dir`, so only changed diagrams are rendered again. A plugin from the plugins
+ * folder with the same name replaces a built-in plugin.
+ */
+impl Diagram {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Diagram::Mermaid => "mermaid",
+            Diagram::Graphviz => "graphviz",
+            Diagram::PlantUml => "plantuml",
+        }
+    }
+
+    pub fn render(
+        &self,
+        text: &str,
+        args: &HashMap<String, String>,
+        options: &serde_json::Value,
+        renderer: &str,
+        cache: &DiagramCache,
+    ) -> Result<String, String> {
+        match self {
+            Diagram::Mermaid => render_mermaid(text, args, options, renderer),
+            Diagram::Graphviz => render_graphviz(text, args, options, cache),
+            Diagram::PlantUml => render_plantuml(text, options, cache),
+        }
+    }
+}
+
+#[cfg(test)]
+fn get_test_cache(name: &str) -> DiagramCache {
+    let dir = std::env::temp_dir().join(format!("fundoc_diagrams_{}", name));
+    fs::remove_dir_all(&dir).ok();
+
+    DiagramCache { dir }
+}
+
+#[test]
+fn render_mermaid_diagrams() {
+    let cache = get_test_cache("mermaid");
+    let args: HashMap<String, String> = vec![(String::from("theme"), String::from("dark"))]
+        .into_iter()
+        .collect();
+
+    let html = Diagram::Mermaid
+        .render(
+            "graph TD; A-->B",
+            &args,
+            &serde_json::Value::Null,
+            "html",
+            &cache,
+        )
+        .unwrap();
+
+    assert!(html.starts_with("<pre class=\"mermaid\">graph TD; A-->B</pre>"));
+    assert!(html.contains("theme: \"dark\""));
+    assert_eq!(
+        Diagram::Mermaid.render(
+            "graph TD; A-->B",
+            &HashMap::new(),
+            &serde_json::Value::Null,
+            "markdown",
+            &cache
+        ),
+        Ok(String::from("```mermaid\ngraph TD; A-->B\n```"))
+    );
+
+    let args: HashMap<String, String> = vec![(
+        String::from("theme"),
+        String::from("\" }); alert(1); ({ \""),
+    )]
+    .into_iter()
+    .collect();
+
+    assert!(Diagram::Mermaid
+        .render(
+            "graph TD; A-->B",
+            &args,
+            &serde_json::Value::Null,
+            "html",
+            &cache
+        )
+        .unwrap_err()
+        .ends_with("isn't a valid Mermaid theme: use one of default, neutral, dark, forest, base"));
+}
+
+#[test]
+fn report_missing_diagram_tools() {
+    let cache = get_test_cache("missing");
+    let options = serde_json::json!({ "command": "fundoc-missing-dot", "jar": "missing.jar" });
+
+    assert_eq!(
+        Diagram::Graphviz.render("digraph {}", &HashMap::new(), &options, "html", &cache),
+        Err(String::from(
+            "Graphviz isn't installed: cannot find the `fundoc-missing-dot` command"
+        ))
+    );
+    assert!(Diagram::PlantUml
+        .render("a -> b", &HashMap::new(), &options, "html", &cache)
+        .unwrap_err()
+        .starts_with("Cannot find the PlantUML jar \"missing.jar\""));
+}
+
+#[test]
+fn read_diagram_commands_only_from_config() {
+    let cache = get_test_cache("commands");
+    let options = serde_json::json!({ "command": "fundoc-missing-dot", "jar": "missing.jar" });
+    let args: HashMap<String, String> = vec![
+        (String::from("command"), String::from("sh")),
+        (String::from("java"), String::from("sh")),
+        (String::from("jar"), String::from("other.jar")),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(
+        Diagram::Graphviz.render("digraph {}", &args, &options, "html", &cache),
+        Err(String::from(
+            "Graphviz isn't installed: cannot find the `fundoc-missing-dot` command"
+        ))
+    );
+    assert!(Diagram::PlantUml
+        .render("a -> b", &args, &options, "html", &cache)
+        .unwrap_err()
+        .starts_with("Cannot find the PlantUML jar \"missing.jar\""));
+}
+
+#[test]
+fn use_cached_diagrams() {
+    let cache = get_test_cache("cached");
+    let options = serde_json::json!({ "command": "fundoc-missing-dot" });
+    let key = get_hash(&["graphviz", "fundoc-missing-dot", "-Tsvg", "digraph {}"]);
+
+    fs::create_dir_all(&cache.dir).unwrap();
+    fs::write(cache.get_path(&key), "<svg></svg>").unwrap();
+
+    assert_eq!(
+        Diagram::Graphviz.render("digraph {}", &HashMap::new(), &options, "html", &cache),
+        Ok(String::from("<svg></svg>"))
+    );
+
+    fs::remove_dir_all(&cache.dir).ok();
+}
+
+#[test]
+fn extract_svg_from_output() {
+    assert_eq!(
+        extract_svg(
+            "<?xml version=\"1.0\"?>\n<svg>\n\n<g/>\n</svg>\n",
+            "Graphviz"
+        ),
+        Ok(String::from("<svg>\n<g/>\n</svg>"))
+    );
+    assert!(extract_svg("Error", "Graphviz").is_err());
+}
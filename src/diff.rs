@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use crate::parser;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LineChange {
+    Unchanged(String),
+    Added(String),
+    Removed(String),
+}
+
+struct Section<'a> {
+    key: (String, usize),
+    article: &'a parser::Article,
+}
+
+pub fn diff_lines(old_text: &str, new_text: &str) -> Vec<LineChange> {
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+    let mut lengths = vec![vec![0usize; new_lines.len() + 1]; old_lines.len() + 1];
+
+    for i in (0..old_lines.len()).rev() {
+        for j in (0..new_lines.len()).rev() {
+            lengths[i][j] = if old_lines[i] == new_lines[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes: Vec<LineChange> = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < old_lines.len() && j < new_lines.len() {
+        if old_lines[i] == new_lines[j] {
+            changes.push(LineChange::Unchanged(old_lines[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            changes.push(LineChange::Removed(old_lines[i].to_string()));
+            i += 1;
+        } else {
+            changes.push(LineChange::Added(new_lines[j].to_string()));
+            j += 1;
+        }
+    }
+
+    changes.extend(
+        old_lines[i..]
+            .iter()
+            .map(|l| LineChange::Removed(l.to_string())),
+    );
+    changes.extend(
+        new_lines[j..]
+            .iter()
+            .map(|l| LineChange::Added(l.to_string())),
+    );
+
+    changes
+}
+
+pub fn format_diff(changes: &[LineChange]) -> String {
+    let mut content = String::from("```diff\n");
+
+    for change in changes {
+        content += &match change {
+            LineChange::Unchanged(line) => format!("  {}\n", line),
+            LineChange::Added(line) => format!("+ {}\n", line),
+            LineChange::Removed(line) => format!("- {}\n", line),
+        };
+    }
+
+    content + "```\n"
+}
+
+fn group_by_topic(articles: &[parser::Article]) -> BTreeMap<&str, Vec<Section<'_>>> {
+    let mut topics: BTreeMap<&str, Vec<Section>> = BTreeMap::new();
+
+    for article in articles {
+        let sections = topics.entry(article.topic.as_str()).or_default();
+        let index = sections
+            .iter()
+            .filter(|section| section.article.path == article.path)
+            .count();
+
+        sections.push(Section {
+            key: (article.path.clone(), index),
+            article,
+        });
+    }
+
+    topics
+}
+
+fn format_source(article: &parser::Article) -> String {
+    format!(
+        "`{}` (lines {}-{})",
+        article.path, article.start_line, article.end_line
+    )
+}
+
+fn format_sources(sections: &[Section]) -> String {
+    let mut paths: Vec<String> = vec![];
+
+    for section in sections {
+        let path = format!("`{}`", section.article.path);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    paths.join(", ")
+}
+
+fn diff_sections(base: &[Section], head: &[Section]) -> String {
+    let mut content = String::from("");
+
+    for section in base {
+        match head.iter().find(|s| s.key == section.key) {
+            Some(head_section) if head_section.article.content != section.article.content => {
+                content += &format!(
+                    "Changed section in {}:\n\n{}\n",
+                    format_source(head_section.article),
+                    format_diff(&diff_lines(
+                        &section.article.content,
+                        &head_section.article.content
+                    ))
+                );
+            }
+            Some(_) => {}
+            None => {
+                content += &format!(
+                    "Removed section in {}:\n\n{}\n",
+                    format_source(section.article),
+                    format_diff(&diff_lines(&section.article.content, ""))
+                );
+            }
+        }
+    }
+
+    for section in head {
+        if !base.iter().any(|s| s.key == section.key) {
+            content += &format!(
+                "Added section in {}:\n\n{}\n",
+                format_source(section.article),
+                format_diff(&diff_lines("", &section.article.content))
+            );
+        }
+    }
+
+    content
+}
+
+/**
+ * @Article Documentation changes
+ *
+ * `fundoc diff <base> <head>` parses the project at both git references and reports which articles
+ * were added, removed or changed. Each changed article contains a diff for every changed section
+ * and a path to the source file the section comes from. The report is written in markdown, so it
+ * can be posted as a comment to a pull request:
+ *
+ * ```bash
+ * fundoc diff origin/master HEAD --output documentation-changes.md
+ * ```
+ */
+pub fn create_report(base: &[parser::Article], head: &[parser::Article]) -> String {
+    let base_topics = group_by_topic(base);
+    let head_topics = group_by_topic(head);
+
+    let mut added = String::from("");
+    let mut removed = String::from("");
+    let mut changed = String::from("");
+
+    for (topic, sections) in &head_topics {
+        if !base_topics.contains_key(topic) {
+            added += &format!("* **{}** ({})\n", topic, format_sources(sections));
+        }
+    }
+
+    for (topic, sections) in &base_topics {
+        match head_topics.get(topic) {
+            Some(head_sections) => {
+                let sections_diff = diff_sections(sections, head_sections);
+
+                if !sections_diff.is_empty() {
+                    changed += &format!("### {}\n\n{}", topic, sections_diff);
+                }
+            }
+            None => removed += &format!("* **{}** ({})\n", topic, format_sources(sections)),
+        }
+    }
+
+    if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        return String::from("# Documentation changes\n\nNo documentation changes\n");
+    }
+
+    let mut report = String::from("# Documentation changes\n");
+
+    if !added.is_empty() {
+        report += &format!("\n## Added articles\n\n{}", added);
+    }
+
+    if !removed.is_empty() {
+        report += &format!("\n## Removed articles\n\n{}", removed);
+    }
+
+    if !changed.is_empty() {
+        report += &format!("\n## Changed articles\n\n{}", changed);
+    }
+
+    report
+}
+
+#[test]
+fn diff_text_lines() {
+    let result = diff_lines("a\nb\nc", "a\nc\nd");
+
+    assert_eq!(
+        result,
+        vec![
+            LineChange::Unchanged(String::from("a")),
+            LineChange::Removed(String::from("b")),
+            LineChange::Unchanged(String::from("c")),
+            LineChange::Added(String::from("d")),
+        ]
+    );
+}
+
+#[test]
+fn report_added_removed_and_changed_articles() {
+    let base = vec![
+        parser::get_test_article("Old", "old", "src/old.rs"),
+        parser::get_test_article("Same", "text", "src/same.rs"),
+        parser::get_test_article("Changed", "a\nb", "src/changed.rs"),
+    ];
+    let head = vec![
+        parser::get_test_article("New", "new", "src/new.rs"),
+        parser::get_test_article("Same", "text", "src/same.rs"),
+        parser::get_test_article("Changed", "a\nc", "src/changed.rs"),
+    ];
+
+    let result = create_report(&base, &head);
+
+    assert_eq!(
+        result,
+        "# Documentation changes\n\n## Added articles\n\n* **New** (`src/new.rs`)\n\n## Removed articles\n\n* **Old** (`src/old.rs`)\n\n## Changed articles\n\n### Changed\n\nChanged section in `src/changed.rs` (lines 1-2):\n\n```diff\n  a\n- b\n+ c\n```\n\n"
+    );
+}
+
+#[test]
+fn report_no_changes() {
+    let articles = vec![parser::get_test_article("Same", "text", "src/same.rs")];
+
+    assert_eq!(
+        create_report(&articles, &articles),
+        "# Documentation changes\n\nNo documentation changes\n"
+    );
+}
@@ -1,6 +1,57 @@
 use std::fs;
+use std::path::{Component, Path, PathBuf};
 
 pub fn recreate_dir(path: &str) -> Result<(), std::io::Error> {
     fs::remove_dir_all(path).ok();
     fs::create_dir_all(path)
 }
+
+pub fn normalize_path(path: &str) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+
+    result
+}
+
+pub fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn relative_path(path: &str, root: &str) -> String {
+    let path = normalize_path(path);
+
+    match path.strip_prefix(normalize_path(root)) {
+        Ok(relative) => to_slash_path(relative),
+        Err(_) => to_slash_path(&path),
+    }
+}
+
+#[test]
+fn get_relative_path() {
+    assert_eq!(relative_path("src/main.rs", "."), "src/main.rs");
+    assert_eq!(
+        relative_path(
+            ".tmp_repositories/fundoc/./src/main.rs",
+            "./.tmp_repositories/fundoc"
+        ),
+        "src/main.rs"
+    );
+    assert_eq!(
+        relative_path("./src/../examples/main.rs", "."),
+        "examples/main.rs"
+    );
+}
@@ -3,25 +3,52 @@ use std::fs::File;
 use std::io::prelude::*;
 
 use crate::config;
+use crate::fs_utils;
+use crate::git;
 use crate::parser;
+use crate::plugins;
+
+const VERSIONS_INDEX: &str = "versions.md";
 
 #[derive(Debug)]
 struct Document {
     title: String,
     file_name: String,
     content: String,
+    last_change: Option<git::Commit>,
+    sections: usize,
+}
+
+/**
+ * @Article Configuration
+ *
+ * Sections and articles with git metadata end with a footer like this:
+ * `Last updated on 2023-04-06 by Sergey Golovin (1a2b3c4)`. An article of a single section has
+ * only the footer of the section.
+ */
+fn format_last_change(commit: &git::Commit) -> String {
+    format!(
+        "_Last updated on {} by {} ({})_",
+        commit.date,
+        commit.author,
+        &commit.sha[..commit.sha.len().min(7)]
+    )
 }
 
 fn to_markdown(document: &Document) -> String {
-    format!("# {}\n{}", document.title, document.content)
+    match &document.last_change {
+        Some(commit) if document.sections > 1 => format!(
+            "# {}\n{}\n---\n\n{}\n",
+            document.title,
+            document.content,
+            format_last_change(commit)
+        ),
+        _ => format!("# {}\n{}", document.title, document.content),
+    }
 }
 
-fn merge_docs(
-    articles: Vec<parser::Article>,
-    repository_host: Option<String>,
-) -> HashMap<String, Document> {
+fn merge_docs(articles: Vec<parser::Article>) -> HashMap<String, Document> {
     let mut documentation: HashMap<String, Document> = HashMap::new();
-    let repository_host = &repository_host;
 
     for article in articles {
         let file_name = article.topic.to_lowercase();
@@ -32,84 +59,447 @@ fn merge_docs(
             title: article.topic.clone(),
             file_name: format!("{}.md", file_name),
             content: "".to_string(),
+            last_change: None,
+            sections: 0,
         });
 
-        let link = match repository_host {
-            Some(host) => format!(
-                "[[~]]({}{}#L{}-L{})",
-                host, article.path, article.start_line, article.end_line
-            ),
+        let link = match &article.link {
+            Some(link) => format!("[[~]]({})", link),
             None => "".to_string(),
         };
 
+        let last_change = match &article.last_change {
+            Some(commit) => format!("\n{}\n", format_last_change(commit)),
+            None => "".to_string(),
+        };
+
+        document.sections += 1;
         document.content = format!(
-            "{}\n{}\n{}\n",
+            "{}\n{}\n{}\n{}",
             document.content,
             article.content.clone(),
-            link
+            link,
+            last_change
         );
+
+        if let Some(commit) = &article.last_change {
+            let is_newer = document
+                .last_change
+                .as_ref()
+                .is_none_or(|last_change| commit.timestamp > last_change.timestamp);
+
+            if is_newer {
+                document.last_change = Some(commit.clone());
+            }
+        }
     }
 
     documentation
 }
 
-fn write_doc(document: &Document, docs_path: &str) {
-    match File::create(format!("{}/{}", docs_path, &document.file_name)) {
+fn write_doc(document: &Document, docs_path: &str) -> Option<String> {
+    let path = format!("{}/{}", docs_path, &document.file_name);
+
+    match File::create(&path) {
         Ok(mut file) => match file.write_all(to_markdown(document).as_bytes()) {
-            Ok(_) => println!("\"{}\" is created", &document.file_name),
+            Ok(_) => {
+                println!("\"{}\" is created", &document.file_name);
+                return Some(path);
+            }
             Err(_) => println!("Cannot write a file: {}", &document.file_name),
         },
         Err(e) => println!("{:?}", e),
     }
+
+    None
+}
+
+#[derive(Debug)]
+struct SummaryItem {
+    title: String,
+    file_name: String,
 }
 
-fn create_summary(documents: &HashMap<String, Document>) -> String {
-    let mut content = String::from("# Summary\n\n");
+fn get_summary_items(documents: &HashMap<String, Document>) -> Vec<SummaryItem> {
     let mut keys = documents.keys().collect::<Vec<_>>();
     keys.sort();
 
-    for key in keys {
-        let document = documents.get(key);
+    keys.iter()
+        .filter_map(|key| documents.get(*key))
+        .map(|document| SummaryItem {
+            title: document.title.clone(),
+            file_name: document.file_name.clone(),
+        })
+        .collect()
+}
 
-        match document {
-            Some(document) => {
-                content += &format!("* [{}](./{})\n", document.title, document.file_name)
-            }
-            None => println!("Cannot find document"),
-        }
+fn format_summary_items(items: &[SummaryItem], dir: &str) -> String {
+    items
+        .iter()
+        .map(|item| format!("* [{}](./{}{})\n", item.title, dir, item.file_name))
+        .collect()
+}
+
+fn format_summary(items: &[SummaryItem]) -> String {
+    format!("# Summary\n\n{}", format_summary_items(items, ""))
+}
+
+fn create_versioned_summary(summaries: &[(String, Vec<SummaryItem>)]) -> String {
+    let mut content = format!("# Summary\n\n[Versions](./{})\n", VERSIONS_INDEX);
+
+    for (version, items) in summaries {
+        content += &format!(
+            "\n# {}\n\n{}",
+            version,
+            format_summary_items(items, &format!("{}/", version))
+        );
+    }
+
+    content
+}
+
+fn create_versions_index(versions: &[(String, HashMap<String, Document>)], mdbook: bool) -> String {
+    let mut content = String::from("# Versions\n\n");
+
+    for (version, documents) in versions {
+        let mut keys = documents.keys().collect::<Vec<_>>();
+        keys.sort();
+
+        let entry_file = if mdbook {
+            keys.first()
+                .and_then(|key| documents.get(*key))
+                .map(|document| document.file_name.clone())
+        } else {
+            Some(String::from("README.md"))
+        };
+
+        content += &match entry_file {
+            Some(file_name) => format!("* [{}](./{}/{})\n", version, version, file_name),
+            None => format!("* {}\n", version),
+        };
     }
 
     content
 }
 
-fn write_summary(documents: &HashMap<String, Document>, docs_path: &str, mdbook: bool) {
-    match File::create(format!(
+/**
+ * @Article Configuration
+ *
+ * In mdBook format every document of a version starts with a version switcher which contains links
+ * to the same document in other versions.
+ */
+fn create_version_switcher(
+    current_version: &str,
+    key: &str,
+    versions: &[(String, HashMap<String, Document>)],
+) -> String {
+    let links: Vec<String> = versions
+        .iter()
+        .map(|(version, documents)| match documents.get(key) {
+            _ if version == current_version => format!("**{}**", version),
+            Some(document) => format!("[{}](../{}/{})", version, version, document.file_name),
+            None => format!("[{}](../{})", version, VERSIONS_INDEX),
+        })
+        .collect();
+
+    format!("Version: {}\n", links.join(" | "))
+}
+
+fn write_summary(content: &str, docs_path: &str, mdbook: bool) -> Option<String> {
+    let path = format!(
         "{}/{}.md",
         docs_path,
         if mdbook { "SUMMARY" } else { "README" }
-    )) {
-        Ok(mut file) => match file.write_all(create_summary(documents).as_bytes()) {
-            Ok(_) => println!("Summary is created",),
+    );
+
+    match File::create(&path) {
+        Ok(mut file) => match file.write_all(content.as_bytes()) {
+            Ok(_) => {
+                println!("Summary is created",);
+                return Some(path);
+            }
             Err(_) => println!("Cannot create the summary file"),
         },
         Err(e) => println!("{:?}", e),
     }
+
+    None
+}
+
+fn write_versions_index(content: &str, docs_path: &str, file_name: &str) -> Option<String> {
+    let path = format!("{}/{}", docs_path, file_name);
+
+    match File::create(&path) {
+        Ok(mut file) => match file.write_all(content.as_bytes()) {
+            Ok(_) => {
+                println!("Versions index is created");
+                return Some(path);
+            }
+            Err(_) => println!("Cannot create the versions index"),
+        },
+        Err(e) => println!("{:?}", e),
+    }
+
+    None
 }
 
-pub fn generate_docs(articles: Vec<parser::Article>, config: config::Config) {
+pub fn generate_versioned_docs(
+    versions_articles: Vec<(String, Vec<parser::Article>)>,
+    config: config::Config,
+    plugins: &plugins::Plugins,
+) -> Result<(), plugins::PluginError> {
     let docs_path = config.docs_folder.unwrap();
-    let documentation = merge_docs(articles, config.repository_host);
+    let mdbook = config.mdbook.unwrap();
+    let mut versions: Vec<(String, HashMap<String, Document>)> = vec![];
+    let mut summaries: Vec<(String, Vec<SummaryItem>)> = vec![];
+    let mut files: Vec<Option<String>> = vec![];
+
+    for (version, articles) in versions_articles {
+        let mut documentation = merge_docs(apply_article_hooks(articles, plugins)?);
+        apply_document_hooks(&mut documentation, plugins)?;
+        let summary = apply_summary_hooks(&mut documentation, plugins)?;
+        apply_plugins(&mut documentation, plugins, mdbook)?;
+
+        summaries.push((version.clone(), summary));
+        versions.push((version, documentation));
+    }
+
+    if mdbook {
+        files.push(write_summary(
+            &create_versioned_summary(&summaries),
+            &docs_path,
+            mdbook,
+        ));
+        files.push(write_versions_index(
+            &create_versions_index(&versions, mdbook),
+            &docs_path,
+            VERSIONS_INDEX,
+        ));
+    } else {
+        files.push(write_versions_index(
+            &create_versions_index(&versions, mdbook),
+            &docs_path,
+            "README.md",
+        ));
+    }
+
+    for ((version, documentation), (_, summary)) in versions.iter().zip(&summaries) {
+        let version_path = format!("{}/{}", docs_path, version);
+
+        if let Err(e) = fs_utils::recreate_dir(&version_path) {
+            println!("{:?}", e);
+            continue;
+        }
+
+        if !mdbook {
+            files.push(write_summary(
+                &format_summary(summary),
+                &version_path,
+                mdbook,
+            ));
+        }
+
+        for (key, document) in documentation {
+            let content = if mdbook {
+                format!(
+                    "\n{}\n{}",
+                    create_version_switcher(version, key, &versions),
+                    document.content
+                )
+            } else {
+                document.content.clone()
+            };
+
+            let document = Document {
+                title: document.title.clone(),
+                file_name: document.file_name.clone(),
+                content,
+                last_change: document.last_change.clone(),
+                sections: document.sections,
+            };
+
+            files.push(write_doc(&document, &version_path));
+        }
+    }
 
-    write_summary(&documentation, &docs_path, config.mdbook.unwrap());
+    apply_finish_hooks(files, plugins)
+}
+
+fn apply_article_hooks(
+    articles: Vec<parser::Article>,
+    plugins: &plugins::Plugins,
+) -> Result<Vec<parser::Article>, plugins::PluginError> {
+    let mut result = vec![];
+
+    for article in articles {
+        if let Some(article) = plugins.on_article(article)? {
+            result.push(article);
+        }
+    }
+
+    Ok(result)
+}
+
+fn apply_document_hooks(
+    documentation: &mut HashMap<String, Document>,
+    plugins: &plugins::Plugins,
+) -> Result<(), plugins::PluginError> {
+    let mut keys = documentation.keys().cloned().collect::<Vec<_>>();
+    keys.sort();
+
+    for key in keys {
+        let Some(document) = documentation.get_mut(&key) else {
+            continue;
+        };
+        let value = serde_json::json!({
+            "title": document.title,
+            "file_name": document.file_name,
+            "content": document.content,
+        });
+
+        match plugins.run_hook("on_document", value)? {
+            Some(value) => {
+                let get_string = |field: &str, default: &str| {
+                    value[field].as_str().unwrap_or(default).to_string()
+                };
+
+                document.title = get_string("title", &document.title);
+                document.file_name = get_string("file_name", &document.file_name);
+                document.content = get_string("content", &document.content);
+            }
+            None => {
+                documentation.remove(&key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_summary_hooks(
+    documentation: &mut HashMap<String, Document>,
+    plugins: &plugins::Plugins,
+) -> Result<Vec<SummaryItem>, plugins::PluginError> {
+    let items = get_summary_items(documentation);
+    let value = serde_json::Value::Array(
+        items
+            .iter()
+            .map(|item| serde_json::json!({ "title": item.title, "file_name": item.file_name }))
+            .collect(),
+    );
+
+    let values = match plugins.run_hook("on_summary", value)? {
+        Some(serde_json::Value::Array(values)) => values,
+        // an empty Lua table can't be distinguished from an empty object
+        Some(serde_json::Value::Object(values)) if values.is_empty() => vec![],
+        Some(_) => {
+            return Err(plugins::PluginError::HookResult {
+                hook: String::from("on_summary"),
+                message: String::from("it isn't a list of summary items"),
+            })
+        }
+        None => vec![],
+    };
+    let mut items = vec![];
+
+    for value in values {
+        let (Some(title), Some(file_name)) = (value["title"].as_str(), value["file_name"].as_str())
+        else {
+            return Err(plugins::PluginError::HookResult {
+                hook: String::from("on_summary"),
+                message: format!("{} doesn't have a title or a file name", value),
+            });
+        };
+
+        if let Some(content) = value["content"].as_str() {
+            let key = file_name.trim_end_matches(".md").to_string();
+
+            if documentation.contains_key(&key) {
+                return Err(plugins::PluginError::SummaryPage {
+                    file_name: file_name.to_string(),
+                });
+            }
+
+            documentation.insert(
+                key,
+                Document {
+                    title: title.to_string(),
+                    file_name: file_name.to_string(),
+                    content: content.to_string(),
+                    last_change: None,
+                    sections: 1,
+                },
+            );
+        }
+
+        items.push(SummaryItem {
+            title: title.to_string(),
+            file_name: file_name.to_string(),
+        });
+    }
+
+    Ok(items)
+}
+
+fn apply_finish_hooks(
+    files: Vec<Option<String>>,
+    plugins: &plugins::Plugins,
+) -> Result<(), plugins::PluginError> {
+    let files: Vec<String> = files.into_iter().flatten().collect();
+
+    plugins.run_hook("on_finish", serde_json::json!(files))?;
+
+    Ok(())
+}
+
+fn apply_plugins(
+    documentation: &mut HashMap<String, Document>,
+    plugins: &plugins::Plugins,
+    mdbook: bool,
+) -> Result<(), plugins::PluginError> {
+    // mdBook runs Fundoc as a preprocessor for every renderer, so blocks are kept for it
+    if mdbook {
+        return Ok(());
+    }
+
+    for document in documentation.values_mut() {
+        let context = plugins::ChapterContext {
+            chapter: document.title.clone(),
+            path: Some(document.file_name.clone()),
+            renderer: String::from("markdown"),
+            last_change: document.last_change.clone(),
+        };
+
+        document.content = plugins.transform(&document.content, &context)?;
+    }
+
+    Ok(())
+}
+
+pub fn generate_docs(
+    articles: Vec<parser::Article>,
+    config: config::Config,
+    plugins: &plugins::Plugins,
+) -> Result<(), plugins::PluginError> {
+    let docs_path = config.docs_folder.unwrap();
+    let mdbook = config.mdbook.unwrap();
+    let mut documentation = merge_docs(apply_article_hooks(articles, plugins)?);
+    apply_document_hooks(&mut documentation, plugins)?;
+    let summary = apply_summary_hooks(&mut documentation, plugins)?;
+    apply_plugins(&mut documentation, plugins, mdbook)?;
+
+    let mut files = vec![write_summary(&format_summary(&summary), &docs_path, mdbook)];
 
     for key in documentation.keys() {
         let document = documentation.get(key);
 
         match document {
-            Some(document) => write_doc(document, &docs_path),
+            Some(document) => files.push(write_doc(document, &docs_path)),
             None => println!("Cannot find the document"),
         }
     }
+
+    apply_finish_hooks(files, plugins)
 }
 
 #[test]
@@ -122,6 +512,8 @@ fn create_sorted_summary() {
             title: "b".to_string(),
             file_name: "".to_string(),
             content: "".to_string(),
+            last_change: None,
+            sections: 0,
         },
     );
 
@@ -131,6 +523,8 @@ fn create_sorted_summary() {
             title: "a".to_string(),
             file_name: "".to_string(),
             content: "".to_string(),
+            last_change: None,
+            sections: 0,
         },
     );
 
@@ -140,10 +534,188 @@ fn create_sorted_summary() {
             title: "c".to_string(),
             file_name: "".to_string(),
             content: "".to_string(),
+            last_change: None,
+            sections: 0,
         },
     );
 
-    let result = create_summary(&documents);
+    let result = format_summary(&get_summary_items(&documents));
 
     assert_eq!(result, "# Summary\n\n* [a](./)\n* [b](./)\n* [c](./)\n");
 }
+
+#[cfg(test)]
+fn get_test_versions() -> Vec<(String, HashMap<String, Document>)> {
+    let mut new_documents: HashMap<String, Document> = HashMap::new();
+    let mut old_documents: HashMap<String, Document> = HashMap::new();
+
+    new_documents.insert(
+        "a".to_string(),
+        Document {
+            title: "A".to_string(),
+            file_name: "a.md".to_string(),
+            content: "".to_string(),
+            last_change: None,
+            sections: 0,
+        },
+    );
+
+    new_documents.insert(
+        "b".to_string(),
+        Document {
+            title: "B".to_string(),
+            file_name: "b.md".to_string(),
+            content: "".to_string(),
+            last_change: None,
+            sections: 0,
+        },
+    );
+
+    old_documents.insert(
+        "a".to_string(),
+        Document {
+            title: "A".to_string(),
+            file_name: "a.md".to_string(),
+            content: "".to_string(),
+            last_change: None,
+            sections: 0,
+        },
+    );
+
+    vec![
+        ("v2.0".to_string(), new_documents),
+        ("v1.0".to_string(), old_documents),
+    ]
+}
+
+#[test]
+fn create_summary_with_versions() {
+    let summaries: Vec<(String, Vec<SummaryItem>)> = get_test_versions()
+        .iter()
+        .map(|(version, documents)| (version.clone(), get_summary_items(documents)))
+        .collect();
+    let result = create_versioned_summary(&summaries);
+
+    assert_eq!(
+        result,
+        "# Summary\n\n[Versions](./versions.md)\n\n# v2.0\n\n* [A](./v2.0/a.md)\n* [B](./v2.0/b.md)\n\n# v1.0\n\n* [A](./v1.0/a.md)\n"
+    );
+}
+
+#[test]
+fn create_switcher_between_versions() {
+    let versions = get_test_versions();
+
+    assert_eq!(
+        create_version_switcher("v2.0", "a", &versions),
+        "Version: **v2.0** | [v1.0](../v1.0/a.md)\n"
+    );
+    assert_eq!(
+        create_version_switcher("v2.0", "b", &versions),
+        "Version: **v2.0** | [v1.0](../versions.md)\n"
+    );
+}
+
+#[test]
+fn add_last_change_footers() {
+    let create_article = |path: &str, sha: &str, date: &str, timestamp: i64| parser::Article {
+        last_change: Some(git::Commit {
+            sha: sha.to_string(),
+            author: "Author".to_string(),
+            date: date.to_string(),
+            timestamp,
+        }),
+        ..parser::get_test_article("Test", "text", path)
+    };
+
+    let documentation = merge_docs(vec![create_article(
+        "src/main.rs",
+        "1fcfba2ccd8e7bffbb0b0d1d9dfd9445bed4e67d",
+        "2023-04-06",
+        1680739200,
+    )]);
+
+    assert_eq!(
+        to_markdown(documentation.get("test").unwrap()),
+        "# Test\n\ntext\n\n\n_Last updated on 2023-04-06 by Author (1fcfba2)_\n"
+    );
+
+    let documentation = merge_docs(vec![
+        create_article(
+            "src/main.rs",
+            "1fcfba2ccd8e7bffbb0b0d1d9dfd9445bed4e67d",
+            "2023-04-06",
+            1680739200,
+        ),
+        create_article(
+            "src/lib.rs",
+            "ef4963a2eefe15ad9bb9cfde48823cc2ce3cd5f1",
+            "2023-04-01",
+            1680307200,
+        ),
+    ]);
+
+    assert_eq!(
+        to_markdown(documentation.get("test").unwrap()),
+        "# Test\n\ntext\n\n\n_Last updated on 2023-04-06 by Author (1fcfba2)_\n\ntext\n\n\n_Last updated on 2023-04-01 by Author (ef4963a)_\n\n---\n\n_Last updated on 2023-04-06 by Author (1fcfba2)_\n"
+    );
+}
+
+#[test]
+fn report_summary_pages_which_replace_documents() {
+    let mut documentation = get_test_versions().remove(0).1;
+    let plugins = plugins::get_test_plugins(
+        "function on_summary(summary)
+          table.insert(summary, 1, { title = 'Glossary', file_name = 'glossary.md', content = 'G' })
+          return summary
+        end",
+    );
+
+    let summary = apply_summary_hooks(&mut documentation, &plugins).unwrap();
+
+    assert_eq!(
+        format_summary(&summary),
+        "# Summary\n\n* [Glossary](./glossary.md)\n* [A](./a.md)\n* [B](./b.md)\n"
+    );
+    assert_eq!(documentation["glossary"].content, "G");
+
+    let plugins = plugins::get_test_plugins(
+        "function on_summary(summary)
+          return { { title = 'A', file_name = 'a.md', content = 'replaced' } }
+        end",
+    );
+
+    assert_eq!(
+        apply_summary_hooks(&mut documentation, &plugins)
+            .unwrap_err()
+            .to_string(),
+        "The \"a.md\" page from on_summary conflicts with another document"
+    );
+    assert_eq!(
+        documentation["a"].content,
+        get_test_versions()[0].1["a"].content
+    );
+}
+
+#[test]
+fn report_invalid_summary_results() {
+    let mut documentation = get_test_versions().remove(0).1;
+    let plugins = plugins::get_test_plugins("function on_summary(summary) return 'summary' end");
+
+    assert_eq!(
+        apply_summary_hooks(&mut documentation, &plugins)
+            .unwrap_err()
+            .to_string(),
+        "Invalid result of on_summary: it isn't a list of summary items"
+    );
+
+    let plugins =
+        plugins::get_test_plugins("function on_summary(summary) return { { title = 'A' } } end");
+
+    assert_eq!(
+        apply_summary_hooks(&mut documentation, &plugins)
+            .unwrap_err()
+            .to_string(),
+        "Invalid result of on_summary: {\"title\":\"A\"} doesn't have a title or a file name"
+    );
+}
@@ -1,11 +1,17 @@
 use ansi_term::Colour;
+use glob::Pattern;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use url::{ParseError, Url};
 
 use crate::config;
 use crate::fs_utils;
+use crate::parser;
 
 #[derive(Debug)]
 pub struct Project {
@@ -13,7 +19,25 @@ pub struct Project {
     pub config: Option<config::Config>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub sha: String,
+    pub author: String,
+    pub date: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug)]
+pub struct CloneError {
+    pub url: String,
+    pub message: String,
+}
+
+pub type CloneResult = Result<Project, CloneError>;
+
 const TMP_REPOSITORIES: &str = "./.tmp_repositories";
+const TMP_VERSIONS: &str = "./.tmp_versions";
+const DEFAULT_CLONE_CONCURRENCY: usize = 4;
 
 fn get_repo_url(url: &str) -> Result<Url, ParseError> {
     Url::parse(url).map(|mut parsed_url| match env::var("GH_TOKEN") {
@@ -27,36 +51,400 @@ fn get_repo_url(url: &str) -> Result<Url, ParseError> {
     })
 }
 
-pub fn clone_repositories(config: config::Config) -> Vec<Project> {
-    config
-        .repositories
-        .unwrap_or_default()
-        .into_iter()
-        .map(|url| {
-            println!("\n{} {}", Colour::Green.bold().paint("Clone"), url);
+fn get_repo_name(url: &str) -> Option<String> {
+    let path = String::from(Url::parse(url).ok()?.path());
+    let start = path.find('/')? + 1;
+    let end = path.rfind(".git").unwrap_or(path.len());
 
-            let path = String::from(Url::parse(&url).unwrap().path());
-            let repo_name = &path[path.find('/').unwrap() + 1..path.rfind(".git").unwrap()];
+    if start >= end {
+        return None;
+    }
 
-            let tmp_dir = format!("{}/{}", TMP_REPOSITORIES, repo_name);
+    Some(path[start..end].to_string())
+}
 
-            fs_utils::recreate_dir(&tmp_dir).ok();
+fn hide_token(message: &str) -> String {
+    match env::var("GH_TOKEN") {
+        Ok(gh_token) if !gh_token.is_empty() => message.replace(&gh_token, "***"),
+        _ => message.to_string(),
+    }
+}
 
-            Command::new("git")
-                .arg("clone")
-                .arg(get_repo_url(&url).unwrap().as_str())
-                .arg(&tmp_dir)
-                .output()
-                .expect("Failed to clone the repo.");
+fn clone_repository(url: &str) -> CloneResult {
+    let clone_error = |message: String| CloneError {
+        url: url.to_string(),
+        message: hide_token(&message),
+    };
 
-            Project {
-                path: tmp_dir.clone(),
-                config: config::read_config(Some(&tmp_dir)),
-            }
-        })
+    let repo_name = get_repo_name(url)
+        .ok_or_else(|| clone_error(String::from("Cannot get a repository name from the url")))?;
+    let repo_url = get_repo_url(url).map_err(|e| clone_error(e.to_string()))?;
+
+    let tmp_dir = format!("{}/{}", TMP_REPOSITORIES, repo_name);
+
+    fs_utils::recreate_dir(&tmp_dir).map_err(|e| clone_error(e.to_string()))?;
+
+    eprintln!("{} {}", Colour::Green.bold().paint("Clone"), url);
+
+    let output = Command::new("git")
+        .arg("clone")
+        .arg("--quiet")
+        .arg(repo_url.as_str())
+        .arg(&tmp_dir)
+        .output()
+        .map_err(|e| clone_error(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(clone_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(Project {
+        path: tmp_dir.clone(),
+        config: config::read_config(Some(&tmp_dir)),
+    })
+}
+
+/**
+ * @Article Configuration
+ *
+ * Repositories from the `repositories` list are cloned in parallel. When all of them are
+ * processed Fundoc prints a report with the result of cloning each repository.
+ */
+pub fn clone_repositories(config: config::Config) -> Vec<CloneResult> {
+    let urls = config.repositories.unwrap_or_default();
+    let next_url = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<CloneResult>>> = Mutex::new(urls.iter().map(|_| None).collect());
+    let concurrency = config
+        .clone_concurrency
+        .unwrap_or(DEFAULT_CLONE_CONCURRENCY)
+        .clamp(1, urls.len().max(1));
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency {
+            scope.spawn(|| loop {
+                let index = next_url.fetch_add(1, Ordering::SeqCst);
+                let Some(url) = urls.get(index) else {
+                    break;
+                };
+
+                let result = clone_repository(url);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
         .collect()
 }
 
+pub fn print_clone_report(results: &[CloneResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    println!("\n{}", Colour::Green.bold().paint("Repositories:"));
+
+    for result in results {
+        match result {
+            Ok(project) => println!("  {} {}", Colour::Green.paint("ok"), project.path),
+            Err(err) => println!(
+                "  {} {}: {}",
+                Colour::Red.paint("failed"),
+                err.url,
+                err.message
+            ),
+        }
+    }
+}
+
 pub fn remove_tmp_repositories() {
     fs::remove_dir_all(TMP_REPOSITORIES).ok();
 }
+
+fn run_git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+pub fn current_revision(root: &str) -> Option<String> {
+    run_git(&["-C", root, "rev-parse", "HEAD"])
+        .ok()
+        .map(|revision| revision.trim().to_string())
+}
+
+pub fn current_branch(root: &str) -> Option<String> {
+    run_git(&["-C", root, "rev-parse", "--abbrev-ref", "HEAD"])
+        .ok()
+        .map(|branch| branch.trim().to_string())
+}
+
+pub fn remote_url(root: &str) -> Option<String> {
+    run_git(&["-C", root, "remote", "get-url", "origin"])
+        .ok()
+        .map(|url| url.trim().to_string())
+}
+
+fn patterns_list(patterns: &[Pattern]) -> String {
+    patterns
+        .iter()
+        .map(|pattern| format!("\"{}\"", pattern))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn filter_versions(refs: &[String], patterns: &[String]) -> Result<Vec<String>, String> {
+    let patterns: Vec<Pattern> = patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .map_err(|e| format!("Invalid version pattern \"{}\": {}", pattern, e))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut versions: Vec<String> = vec![];
+
+    for reference in refs {
+        if patterns.iter().any(|pattern| pattern.matches(reference))
+            && !versions.contains(reference)
+        {
+            versions.push(reference.clone());
+        }
+    }
+
+    if versions.is_empty() {
+        return Err(format!(
+            "No git references match the versions: {}",
+            patterns_list(&patterns)
+        ));
+    }
+
+    Ok(versions)
+}
+
+/**
+ * @Article Configuration
+ *
+ * Versions are sorted by the date of their last commit, so the newest version goes first. If none
+ * of git references match `versions`, Fundoc stops with an error. A version which can't be checked
+ * out or parsed is reported and skipped.
+ */
+pub fn list_versions(patterns: &[String]) -> Result<Vec<String>, String> {
+    let refs = run_git(&[
+        "for-each-ref",
+        "--sort=-committerdate",
+        "--format=%(refname:short)",
+        "refs/tags",
+        "refs/heads",
+    ])
+    .map_err(|e| format!("Cannot list git references: {}", e))?;
+    let refs: Vec<String> = refs.lines().map(String::from).collect();
+
+    filter_versions(&refs, patterns)
+}
+
+pub fn version_name(reference: &str) -> String {
+    reference.replace('/', "-")
+}
+
+pub fn checkout_version(reference: &str) -> Result<String, String> {
+    let path = format!("{}/{}", TMP_VERSIONS, version_name(reference));
+
+    fs::remove_dir_all(&path).ok();
+    run_git(&["worktree", "add", "--detach", "--force", &path, reference])?;
+
+    Ok(path)
+}
+
+fn is_commit_header(line: &str) -> bool {
+    line.len() > 40
+        && line.as_bytes()[40] == b' '
+        && line[..40].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn parse_blame(blame: &str) -> Option<(String, i64)> {
+    let mut current_sha = String::from("");
+    let mut last_commit: Option<(String, i64)> = None;
+
+    for line in blame.lines() {
+        if is_commit_header(line) {
+            current_sha = line[..40].to_string();
+        } else if let Some(time) = line.strip_prefix("committer-time ") {
+            let time: i64 = time.trim().parse().unwrap_or(0);
+            let is_committed = current_sha.chars().any(|c| c != '0');
+            let is_newer = last_commit.as_ref().is_none_or(|(_, t)| time > *t);
+
+            if is_committed && is_newer {
+                last_commit = Some((current_sha.clone(), time));
+            }
+        }
+    }
+
+    last_commit
+}
+
+fn get_commit(root: &str, sha: &str, timestamp: i64) -> Option<Commit> {
+    let output = run_git(&[
+        "-C",
+        root,
+        "show",
+        "--no-patch",
+        "--date=short",
+        "--format=%an%x00%cd",
+        sha,
+    ])
+    .ok()?;
+    let (author, date) = output.trim().split_once('\0')?;
+
+    Some(Commit {
+        sha: sha.to_string(),
+        author: author.to_string(),
+        date: date.to_string(),
+        timestamp,
+    })
+}
+
+fn blame_lines(root: &str, path: &str, start_line: i16, end_line: i16) -> Option<(String, i64)> {
+    let lines = format!("{},{}", start_line, end_line.max(start_line));
+    let mut args = vec!["-C", root, "blame", "--porcelain"];
+
+    if !path.ends_with(".fdoc.md") {
+        args.append(&mut vec!["-L", &lines]);
+    }
+    args.append(&mut vec!["--", path]);
+
+    parse_blame(&run_git(&args).ok()?)
+}
+
+pub fn last_commit(root: &str, path: &str, start_line: i16, end_line: i16) -> Option<Commit> {
+    let (sha, timestamp) = blame_lines(root, path, start_line, end_line)?;
+
+    get_commit(root, &sha, timestamp)
+}
+
+/**
+ * @Article Configuration
+ *
+ * Metadata of a section is taken from the newest commit which changed lines of the section
+ * according to `git blame`. Uncommitted changes are ignored.
+ */
+pub fn annotate_articles(articles: &mut [parser::Article], root: &str) {
+    let mut commits: HashMap<String, Option<Commit>> = HashMap::new();
+
+    for article in articles.iter_mut() {
+        let Some((sha, timestamp)) =
+            blame_lines(root, &article.path, article.start_line, article.end_line)
+        else {
+            continue;
+        };
+
+        article.last_change = commits
+            .entry(sha.clone())
+            .or_insert_with(|| get_commit(root, &sha, timestamp))
+            .clone();
+    }
+}
+
+pub fn remove_versions() {
+    fs::remove_dir_all(TMP_VERSIONS).ok();
+    run_git(&["worktree", "prune"]).ok();
+}
+
+#[test]
+fn get_repo_name_from_url() {
+    assert_eq!(
+        get_repo_name("https://github.com/daynin/fundoc.git"),
+        Some(String::from("daynin/fundoc"))
+    );
+    assert_eq!(
+        get_repo_name("https://github.com/daynin/fundoc"),
+        Some(String::from("daynin/fundoc"))
+    );
+    assert_eq!(get_repo_name("https://github.com/"), None);
+}
+
+#[test]
+fn filter_versions_by_patterns() {
+    let refs: Vec<String> = vec!["v2.0", "main", "feature/test", "v1.0", "main"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let patterns = vec![String::from("v*"), String::from("main")];
+
+    assert_eq!(
+        filter_versions(&refs, &patterns),
+        Ok(vec![
+            String::from("v2.0"),
+            String::from("main"),
+            String::from("v1.0")
+        ])
+    );
+}
+
+#[test]
+fn report_invalid_version_patterns() {
+    let refs = vec![String::from("v1.0")];
+    let patterns = vec![String::from("v*"), String::from("v[1")];
+
+    assert!(filter_versions(&refs, &patterns)
+        .unwrap_err()
+        .starts_with("Invalid version pattern \"v[1\""));
+}
+
+#[test]
+fn parse_newest_commit_from_blame() {
+    let blame = "ef4963a2eefe15ad9bb9cfde48823cc2ce3cd5f1 2 2 1
+author a
+committer-time 1792367003
+\t * @Article Intro
+1fcfba2ccd8e7bffbb0b0d1d9dfd9445bed4e67d 3 3 1
+author b
+committer-time 1792367004
+\t * new text
+0000000000000000000000000000000000000000 4 4 1
+author Not Committed Yet
+committer-time 1792367005
+\t * uncommitted text
+";
+
+    assert_eq!(
+        parse_blame(blame),
+        Some((
+            String::from("1fcfba2ccd8e7bffbb0b0d1d9dfd9445bed4e67d"),
+            1792367004
+        ))
+    );
+}
+
+#[test]
+fn report_invalid_repository_url() {
+    let result = clone_repository("not a url");
+
+    assert_eq!(result.unwrap_err().url, "not a url");
+}
+
+#[test]
+fn report_versions_without_references() {
+    let refs = vec![String::from("v1.0"), String::from("main")];
+    let patterns = vec![String::from("release-*"), String::from("stable")];
+
+    assert_eq!(
+        filter_versions(&refs, &patterns),
+        Err(String::from(
+            "No git references match the versions: \"release-*\", \"stable\""
+        ))
+    );
+}
@@ -0,0 +1,305 @@
+use regex::Regex;
+use url::Url;
+
+use crate::config;
+use crate::git;
+use crate::parser;
+
+const DEFAULT_PRESET: &str = "github";
+const PRESETS: [&str; 5] = ["github", "gitlab", "bitbucket", "gitea", "azure"];
+
+pub struct Source {
+    host: String,
+    template: String,
+    revision: Option<String>,
+    pinned: bool,
+}
+
+fn get_preset(name: &str, pinned: bool) -> Option<&'static str> {
+    match name {
+        "github" | "gitea" => Some("{host}/{path}#L{start}-L{end}"),
+        "gitlab" => Some("{host}/{path}#L{start}-{end}"),
+        "bitbucket" => Some("{host}/{path}#lines-{start}:{end}"),
+        "azure" if pinned => Some("{host}?path=/{path}&version=GC{ref}&line={start}&lineEnd={end}&lineStartColumn=1&lineEndColumn=1"),
+        "azure" => Some("{host}?path=/{path}&version=GB{ref}&line={start}&lineEnd={end}&lineStartColumn=1&lineEndColumn=1"),
+        _ => None,
+    }
+}
+
+// remotes can be written in the scp-like form, e.g. `git@gitlab.com:team/project.git`
+fn parse_url(url: &str) -> Option<(String, String)> {
+    if let Ok(url) = Url::parse(url) {
+        return url
+            .host_str()
+            .map(|host| (host.to_string(), url.path().to_string()));
+    }
+
+    let (host, path) = url.split_once(':')?;
+    let host = host.rsplit('@').next()?;
+
+    Some((host.to_string(), format!("/{}", path)))
+}
+
+fn detect_preset(host: &str, remote: Option<&str>) -> &'static str {
+    let presets = [
+        ("github.com", "github"),
+        ("gitlab", "gitlab"),
+        ("bitbucket", "bitbucket"),
+        ("dev.azure.com", "azure"),
+        ("visualstudio.com", "azure"),
+        ("gitea", "gitea"),
+        ("codeberg.org", "gitea"),
+    ];
+
+    for (host, path) in [Some(host), remote]
+        .iter()
+        .flatten()
+        .filter_map(|url| parse_url(url))
+    {
+        // self-hosted GitLab instances can have any domain
+        if path.contains("/-/blob/") {
+            return "gitlab";
+        }
+
+        for (pattern, preset) in presets {
+            if host.contains(pattern) {
+                return preset;
+            }
+        }
+    }
+
+    DEFAULT_PRESET
+}
+
+/**
+ * @Article Configuration
+ *
+ * With `permalinks` enabled, a branch name in `repository_host` is replaced by the current commit
+ * of a project. For example, `https://github.com/user_name/project_name/blob/master/` becomes
+ * `https://github.com/user_name/project_name/blob/4f1c2a8.../`. Hosts with `blob/<branch>`
+ * (GitHub, GitLab, Gitea) and `src/<branch>` (Bitbucket) are supported.
+ */
+pub fn pin_host(host: &str, revision: &str) -> String {
+    let re = Regex::new(r"/(blob|tree|src)/[^/]+(/?)$").unwrap();
+
+    re.replace(host, |captures: &regex::Captures| {
+        format!("/{}/{}{}", &captures[1], revision, &captures[2])
+    })
+    .to_string()
+}
+
+impl Source {
+    pub fn new(config: &config::Config, root: &str) -> Result<Option<Self>, String> {
+        let Some(host) = config.repository_host.clone() else {
+            return Ok(None);
+        };
+        let pinned = config.permalinks.unwrap_or(false);
+
+        let template = match &config.source_link_template {
+            Some(template) => match get_preset(template, pinned) {
+                Some(preset) => preset.to_string(),
+                None if template.contains("{path}") => template.clone(),
+                None => {
+                    return Err(format!(
+                        "\"{}\" isn't a valid source_link_template: use one of the presets ({}) or a template with {{path}}",
+                        template,
+                        PRESETS.join(", ")
+                    ))
+                }
+            },
+            None => {
+                let remote = git::remote_url(root);
+                let preset = detect_preset(&host, remote.as_deref());
+
+                get_preset(preset, pinned).unwrap().to_string()
+            }
+        };
+
+        let revision = if pinned {
+            git::current_revision(root)
+        } else if template.contains("{ref}") {
+            git::current_branch(root)
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            host,
+            template,
+            revision,
+            pinned,
+        }))
+    }
+
+    pub fn create_link(&self, article: &parser::Article) -> String {
+        self.create_range_link(
+            &article.path,
+            article.start_line as usize,
+            article.end_line as usize,
+        )
+    }
+
+    pub fn create_range_link(&self, path: &str, start_line: usize, end_line: usize) -> String {
+        let host = match (&self.revision, self.pinned) {
+            (Some(revision), true) => pin_host(&self.host, revision),
+            _ => self.host.clone(),
+        };
+
+        self.template
+            .replace("{host}", host.trim_end_matches('/'))
+            .replace("{path}", path)
+            .replace("{start}", &start_line.to_string())
+            .replace("{end}", &end_line.to_string())
+            .replace("{ref}", self.revision.as_deref().unwrap_or(""))
+    }
+}
+
+#[cfg(test)]
+fn get_test_source(host: &str, preset: &str, revision: Option<&str>, pinned: bool) -> Source {
+    Source {
+        host: String::from(host),
+        template: get_preset(preset, pinned).unwrap().to_string(),
+        revision: revision.map(String::from),
+        pinned,
+    }
+}
+
+#[test]
+fn pin_host_to_commit() {
+    let sha = "1fcfba2ccd8e7bffbb0b0d1d9dfd9445bed4e67d";
+
+    assert_eq!(
+        pin_host("https://github.com/daynin/fundoc/blob/master/", sha),
+        format!("https://github.com/daynin/fundoc/blob/{}/", sha)
+    );
+    assert_eq!(
+        pin_host("https://gitlab.com/daynin/fundoc/-/blob/main", sha),
+        format!("https://gitlab.com/daynin/fundoc/-/blob/{}", sha)
+    );
+    assert_eq!(
+        pin_host("https://bitbucket.org/daynin/fundoc/src/master/", sha),
+        format!("https://bitbucket.org/daynin/fundoc/src/{}/", sha)
+    );
+    assert_eq!(
+        pin_host("https://example.com/files/", sha),
+        "https://example.com/files/"
+    );
+}
+
+#[test]
+fn detect_presets_from_urls() {
+    assert_eq!(
+        detect_preset("https://github.com/daynin/fundoc/blob/master/", None),
+        "github"
+    );
+    assert_eq!(
+        detect_preset(
+            "https://git.company.com/team/fundoc/-/blob/master/",
+            Some("git@git.company.com:team/fundoc.git")
+        ),
+        "gitlab"
+    );
+    assert_eq!(
+        detect_preset(
+            "https://code.company.com/fundoc/",
+            Some("https://bitbucket.org/team/fundoc.git")
+        ),
+        "bitbucket"
+    );
+    assert_eq!(
+        detect_preset("https://dev.azure.com/org/project/_git/fundoc", None),
+        "azure"
+    );
+    assert_eq!(
+        detect_preset(
+            "https://github.com/org/gitlab-runner/blob/master/",
+            Some("git@github.com:org/gitlab-runner.git")
+        ),
+        "github"
+    );
+    assert_eq!(
+        detect_preset(
+            "https://code.company.com/gitea-tools/blob/master/",
+            Some("git@gitlab.company.com:team/bitbucket-sync.git")
+        ),
+        "gitlab"
+    );
+}
+
+#[test]
+fn create_links_from_presets() {
+    let article = parser::Article {
+        start_line: 10,
+        end_line: 20,
+        ..parser::get_test_article("Test", "", "src/main.rs")
+    };
+
+    assert_eq!(
+        get_test_source(
+            "https://github.com/daynin/fundoc/blob/master/",
+            "github",
+            None,
+            false
+        )
+        .create_link(&article),
+        "https://github.com/daynin/fundoc/blob/master/src/main.rs#L10-L20"
+    );
+    assert_eq!(
+        get_test_source(
+            "https://github.com/daynin/fundoc/blob/master/",
+            "github",
+            Some("1fcfba2"),
+            true
+        )
+        .create_link(&article),
+        "https://github.com/daynin/fundoc/blob/1fcfba2/src/main.rs#L10-L20"
+    );
+    assert_eq!(
+        get_test_source(
+            "https://gitlab.com/daynin/fundoc/-/blob/main/",
+            "gitlab",
+            None,
+            false
+        )
+        .create_link(&article),
+        "https://gitlab.com/daynin/fundoc/-/blob/main/src/main.rs#L10-20"
+    );
+    assert_eq!(
+        get_test_source(
+            "https://bitbucket.org/daynin/fundoc/src/master/",
+            "bitbucket",
+            None,
+            false
+        )
+        .create_link(&article),
+        "https://bitbucket.org/daynin/fundoc/src/master/src/main.rs#lines-10:20"
+    );
+    assert_eq!(
+        get_test_source(
+            "https://dev.azure.com/org/project/_git/fundoc",
+            "azure",
+            Some("main"),
+            false
+        )
+        .create_link(&article),
+        "https://dev.azure.com/org/project/_git/fundoc?path=/src/main.rs&version=GBmain&line=10&lineEnd=20&lineStartColumn=1&lineEndColumn=1"
+    );
+}
+
+#[test]
+fn validate_source_link_templates() {
+    let mut config = crate::parser::get_test_config();
+    config.repository_host = Some(String::from("https://gitlab.com/user/project/-/blob/main"));
+
+    config.source_link_template = Some(String::from("gitlab"));
+    assert!(Source::new(&config, ".").unwrap().is_some());
+
+    config.source_link_template = Some(String::from("{host}/{path}?line={start}"));
+    assert!(Source::new(&config, ".").unwrap().is_some());
+
+    config.source_link_template = Some(String::from("gitlub"));
+    assert!(Source::new(&config, ".")
+        .err()
+        .unwrap()
+        .starts_with("\"gitlub\" isn't a valid source_link_template"));
+}
@@ -0,0 +1,202 @@
+use glob::glob;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config;
+use crate::fs_utils;
+
+/**
+ * @Article Plugins
+ *
+ * Plugins can read files of the project with the `fs` table:
+ *
+ * - `fs.read(path)` - returns content of a file
+ * - `fs.exists(path)` - returns true if a file or a directory exists
+ * - `fs.list(path)` - returns a sorted list of names of files in a directory
+ * - `fs.glob(pattern)` - returns a sorted list of paths matching a unix style pattern
+ * - `fs.write(path, content)` - writes a file
+ *
+ * ```lua
+ * function transform(text)
+ *   return '<pre>' .. fs.read('examples/' .. text) .. '</pre>'
+ * end
+ * ```
+ *
+ * Relative paths are resolved from the project root. Files outside the project root can't be
+ * accessed and writing is disabled unless `plugins_fs_outside_root` and `plugins_fs_write` are
+ * enabled in the config file.
+ */
+#[derive(Debug, Clone)]
+pub struct FsSandbox {
+    root: PathBuf,
+    allow_write: bool,
+    allow_outside_root: bool,
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+
+    result
+}
+
+fn resolve_links(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest: Vec<&std::ffi::OsStr> = vec![];
+
+    while !existing.exists() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            return path.to_path_buf();
+        };
+        rest.push(name);
+        existing = parent;
+    }
+
+    let mut result = existing
+        .canonicalize()
+        .unwrap_or_else(|_| existing.to_path_buf());
+    result.extend(rest.iter().rev());
+
+    result
+}
+
+impl FsSandbox {
+    pub fn new(root: &Path, config: &config::Config) -> Self {
+        Self {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            allow_write: config.plugins_fs_write.unwrap_or(false),
+            allow_outside_root: config.plugins_fs_outside_root.unwrap_or(false),
+        }
+    }
+
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let resolved = resolve_links(&normalize(&self.root.join(path)));
+
+        if !self.allow_outside_root && !resolved.starts_with(&self.root) {
+            return Err(format!(
+                "Access to \"{}\" is denied: it's outside the project root",
+                path
+            ));
+        }
+
+        Ok(resolved)
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => fs_utils::to_slash_path(relative),
+            Err(_) => path.to_string_lossy().to_string(),
+        }
+    }
+
+    pub fn read(&self, path: &str) -> Result<String, String> {
+        fs::read_to_string(self.resolve(path)?)
+            .map_err(|e| format!("Cannot read \"{}\": {}", path, e))
+    }
+
+    pub fn exists(&self, path: &str) -> Result<bool, String> {
+        Ok(self.resolve(path)?.exists())
+    }
+
+    pub fn list(&self, path: &str) -> Result<Vec<String>, String> {
+        let entries = fs::read_dir(self.resolve(path)?)
+            .map_err(|e| format!("Cannot list \"{}\": {}", path, e))?;
+        let mut names: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+        let pattern = normalize(&self.root.join(pattern));
+        let entries = glob(&pattern.to_string_lossy())
+            .map_err(|e| format!("Invalid pattern \"{}\": {}", pattern.display(), e))?;
+        let mut paths: Vec<String> = entries
+            .flatten()
+            .map(|path| resolve_links(&path))
+            .filter(|path| self.allow_outside_root || path.starts_with(&self.root))
+            .map(|path| self.display_path(&path))
+            .collect();
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    pub fn write(&self, path: &str, content: &str) -> Result<(), String> {
+        if !self.allow_write {
+            return Err(format!(
+                "Writing \"{}\" is denied: enable `plugins_fs_write` to allow plugins to write files",
+                path
+            ));
+        }
+
+        fs::write(self.resolve(path)?, content)
+            .map_err(|e| format!("Cannot write \"{}\": {}", path, e))
+    }
+}
+
+#[cfg(test)]
+fn get_test_sandbox(name: &str) -> (PathBuf, FsSandbox) {
+    let dir = std::env::temp_dir().join(format!("fundoc_lua_fs_{}", name));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(dir.join("root/data")).unwrap();
+    fs::write(dir.join("root/data/a.csv"), "a,b").unwrap();
+    fs::write(dir.join("root/data/b.json"), "{}").unwrap();
+    fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+    let sandbox = FsSandbox::new(&dir.join("root"), &crate::parser::get_test_config());
+
+    (dir, sandbox)
+}
+
+#[test]
+fn read_files_inside_root() {
+    let (dir, sandbox) = get_test_sandbox("read");
+
+    assert_eq!(sandbox.read("data/a.csv"), Ok(String::from("a,b")));
+    assert_eq!(sandbox.exists("./data/../data/b.json"), Ok(true));
+    assert_eq!(sandbox.exists("data/c.json"), Ok(false));
+    assert_eq!(
+        sandbox.list("data"),
+        Ok(vec![String::from("a.csv"), String::from("b.json")])
+    );
+    assert_eq!(
+        sandbox.glob("**/*.csv"),
+        Ok(vec![String::from("data/a.csv")])
+    );
+
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn deny_access_outside_root_and_writes() {
+    let (dir, mut sandbox) = get_test_sandbox("deny");
+
+    assert!(sandbox.read("../secret.txt").is_err());
+    assert!(sandbox
+        .read(&dir.join("secret.txt").to_string_lossy())
+        .is_err());
+    assert_eq!(sandbox.glob("../*.txt"), Ok(vec![]));
+    assert!(sandbox.write("data/c.txt", "c").is_err());
+
+    sandbox.allow_write = true;
+    sandbox.allow_outside_root = true;
+
+    assert_eq!(sandbox.read("../secret.txt"), Ok(String::from("secret")));
+    assert_eq!(sandbox.write("data/c.txt", "c"), Ok(()));
+    assert_eq!(sandbox.read("data/c.txt"), Ok(String::from("c")));
+
+    fs::remove_dir_all(dir).ok();
+}
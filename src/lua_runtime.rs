@@ -1,18 +1,151 @@
-use rlua::{Function, Lua, Result};
+use regex::Regex;
+use rlua::{
+    Context, Error, FromLua, Function, HookTriggers, Lua, MultiValue, RegistryKey, Result, StdLib,
+    Table, Value,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::lua_fs;
+
+fn to_lua_value<'lua>(ctx: Context<'lua>, value: &serde_json::Value) -> Result<Value<'lua>> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(value) => Value::Boolean(*value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(number) => Value::Integer(number),
+            None => Value::Number(number.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(value) => Value::String(ctx.create_string(value)?),
+        serde_json::Value::Array(values) => {
+            let table = ctx.create_table()?;
+            for (index, value) in values.iter().enumerate() {
+                table.set(index + 1, to_lua_value(ctx, value)?)?;
+            }
+            Value::Table(table)
+        }
+        serde_json::Value::Object(values) => {
+            let table = ctx.create_table()?;
+            for (key, value) in values {
+                table.set(key.as_str(), to_lua_value(ctx, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+const MAX_TABLE_DEPTH: usize = 64;
+
+fn from_lua_value(value: Value, depth: usize) -> Result<serde_json::Value> {
+    if depth > MAX_TABLE_DEPTH {
+        return Err(Error::RuntimeError(String::from(
+            "the returned table is nested too deeply",
+        )));
+    }
+
+    Ok(match value {
+        Value::Boolean(value) => serde_json::Value::Bool(value),
+        Value::Integer(number) => serde_json::Value::from(number),
+        Value::Number(number) => serde_json::Value::from(number),
+        Value::String(value) => serde_json::Value::String(value.to_str()?.to_string()),
+        Value::Table(table) => {
+            let length = table.raw_len() as usize;
+            let pairs = table
+                .clone()
+                .pairs::<Value, Value>()
+                .collect::<Result<Vec<_>>>()?;
+
+            if length > 0 && pairs.len() == length {
+                let mut values = vec![];
+                for value in table.sequence_values::<Value>() {
+                    values.push(from_lua_value(value?, depth + 1)?);
+                }
+                serde_json::Value::Array(values)
+            } else {
+                let mut values = serde_json::Map::new();
+                for (key, value) in pairs {
+                    let key = match key {
+                        Value::String(key) => key.to_str()?.to_string(),
+                        Value::Integer(key) => key.to_string(),
+                        _ => continue,
+                    };
+                    values.insert(key, from_lua_value(value, depth + 1)?);
+                }
+                serde_json::Value::Object(values)
+            }
+        }
+        _ => serde_json::Value::Null,
+    })
+}
+
+pub fn describe_error(err: &Error) -> (Option<usize>, String) {
+    if let Error::CallbackError { cause, traceback } = err {
+        let (_, message) = describe_error(cause);
+        let re = Regex::new(r":(\d+): in ").unwrap();
+        let line = re
+            .captures(traceback)
+            .and_then(|captures| captures[1].parse().ok());
+
+        return (line, message);
+    }
+
+    let message = match err {
+        Error::RuntimeError(message) => message.clone(),
+        Error::SyntaxError { message, .. } => message.clone(),
+        err => err.to_string(),
+    };
+    let message = message.lines().next().unwrap_or("").to_string();
+    let re = Regex::new(r":(\d+): (.*)$").unwrap();
+
+    match re.captures(&message) {
+        Some(captures) => (captures[1].parse().ok(), captures[2].to_string()),
+        None => (None, message),
+    }
+}
+
+fn copy_table<'lua>(ctx: Context<'lua>, table: Table<'lua>) -> Result<Table<'lua>> {
+    let copy = ctx.create_table()?;
+
+    for pair in table.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        copy.set(key, value)?;
+    }
+
+    Ok(copy)
+}
+
+const INSTRUCTIONS_STEP: u32 = 1000;
 
 pub struct LuaRuntime {
     runtime: Lua,
+    instructions: Arc<AtomicU64>,
 }
 
+/**
+ * @Article Plugins
+ *
+ * Plugins run in a sandbox. Only `string`, `table`, `math`, `utf8` and `coroutine` libraries of
+ * the Lua standard library are available, so plugins can't run commands or access files except via
+ * the `fs` table. Each call of a plugin can execute a limited number of Lua instructions and all
+ * plugins share a memory limit. The limits can be changed with `plugins_instruction_limit` and
+ * `plugins_memory_limit_mb` in the config file.
+ *
+ * Every plugin has its own copy of global variables and libraries, so a plugin can't change them
+ * for other plugins. `print` writes to stderr, so it doesn't break the output of the preprocessor.
+ */
 impl LuaRuntime {
     pub fn new() -> Self {
-        Self {
-            runtime: Lua::new(),
-        }
-    }
+        let runtime = Lua::new_with(
+            StdLib::BASE
+                | StdLib::COROUTINE
+                | StdLib::TABLE
+                | StdLib::STRING
+                | StdLib::UTF8
+                | StdLib::MATH,
+        );
 
-    pub fn exec(&self, lua_code: String) {
-        self.runtime.context(|ctx| {
+        runtime.context(|ctx| {
             let globals = ctx.globals();
             let log = ctx
                 .create_function(|_, msg: String| {
@@ -24,21 +157,511 @@ impl LuaRuntime {
                 eprintln!("{:#?}", err)
             }
 
-            if let Err(err) = ctx.load(&lua_code).exec() {
-                eprintln!("{:#?}", err)
+            // stdout is reserved for the book when Fundoc runs as an mdBook preprocessor
+            let print = ctx
+                .create_function(|ctx, values: MultiValue| {
+                    let tostring: Function = ctx.globals().get("tostring")?;
+                    let mut line: Vec<String> = vec![];
+
+                    for value in values {
+                        line.push(tostring.call(value)?);
+                    }
+                    eprintln!("{}", line.join("\t"));
+
+                    Ok(())
+                })
+                .unwrap();
+            globals.set("print", print).ok();
+
+            // the metatable of strings refers to the shared `string` library
+            ctx.load("getmetatable('').__metatable = false").exec().ok();
+
+            for name in ["dofile", "loadfile", "load"].iter() {
+                globals.set(*name, Value::Nil).ok();
             }
         });
+
+        Self {
+            runtime,
+            instructions: Arc::new(AtomicU64::new(0)),
+        }
     }
 
-    pub fn call_transform(&self, text: String) -> Result<String> {
+    pub fn set_limits(&self, instruction_limit: Option<u64>, memory_limit: Option<usize>) {
+        self.runtime.set_memory_limit(memory_limit);
+
+        let Some(limit) = instruction_limit else {
+            self.runtime.remove_hook();
+            return;
+        };
+        let instructions = self.instructions.clone();
+
+        self.runtime.set_hook(
+            HookTriggers {
+                every_nth_instruction: Some(INSTRUCTIONS_STEP),
+                ..HookTriggers::default()
+            },
+            move |_, _| {
+                let count = instructions.fetch_add(INSTRUCTIONS_STEP as u64, Ordering::SeqCst);
+
+                if count + INSTRUCTIONS_STEP as u64 > limit {
+                    Err(Error::RuntimeError(format!(
+                        "the plugin exceeded the limit of {} instructions",
+                        limit
+                    )))
+                } else {
+                    Ok(())
+                }
+            },
+        );
+    }
+
+    pub fn set_fs(&self, sandbox: lua_fs::FsSandbox) -> Result<()> {
         self.runtime.context(|ctx| {
-            let transform: Function = ctx.globals().get("transform")?;
+            let fs = ctx.create_table()?;
 
-            if let Err(err) = transform.call::<_, ()>(text) {
-                eprintln!("{:#?}", err)
+            let read_sandbox = sandbox.clone();
+            fs.set(
+                "read",
+                ctx.create_function(move |_, path: String| {
+                    read_sandbox.read(&path).map_err(Error::RuntimeError)
+                })?,
+            )?;
+
+            let exists_sandbox = sandbox.clone();
+            fs.set(
+                "exists",
+                ctx.create_function(move |_, path: String| {
+                    exists_sandbox.exists(&path).map_err(Error::RuntimeError)
+                })?,
+            )?;
+
+            let list_sandbox = sandbox.clone();
+            fs.set(
+                "list",
+                ctx.create_function(move |_, path: String| {
+                    list_sandbox.list(&path).map_err(Error::RuntimeError)
+                })?,
+            )?;
+
+            let glob_sandbox = sandbox.clone();
+            fs.set(
+                "glob",
+                ctx.create_function(move |_, pattern: String| {
+                    glob_sandbox.glob(&pattern).map_err(Error::RuntimeError)
+                })?,
+            )?;
+
+            fs.set(
+                "write",
+                ctx.create_function(move |_, (path, content): (String, String)| {
+                    sandbox.write(&path, &content).map_err(Error::RuntimeError)
+                })?,
+            )?;
+
+            ctx.globals().set("fs", fs)
+        })
+    }
+
+    // every plugin is compiled once into its own environment with its own copies of the globals
+    // and library tables, so plugins don't share globals
+    pub fn load(&self, file: &str, lua_code: &str) -> Result<RegistryKey> {
+        self.instructions.store(0, Ordering::SeqCst);
+        self.runtime.context(|ctx| {
+            let environment = ctx.create_table()?;
+
+            for pair in ctx.globals().pairs::<String, Value>() {
+                let (name, value) = pair?;
+                let value = match value {
+                    Value::Table(_) if name == "_G" => Value::Table(environment.clone()),
+                    Value::Table(library) => Value::Table(copy_table(ctx, library)?),
+                    value => value,
+                };
+                environment.set(name, value)?;
+            }
+
+            ctx.load(lua_code)
+                .set_name(&format!("@{}", file))?
+                .set_environment(environment.clone())?
+                .exec()?;
+
+            ctx.create_registry_value(environment)
+        })
+    }
+
+    pub fn keyword_names(&self, plugin: &RegistryKey) -> Result<Vec<String>> {
+        self.runtime.context(|ctx| {
+            let environment: Table = ctx.registry_value(plugin)?;
+            let Some(keywords) = environment.get::<_, Option<Table>>("keywords")? else {
+                return Ok(vec![]);
+            };
+            let mut names: Vec<String> = vec![];
+
+            for pair in keywords.pairs::<String, Function>() {
+                names.push(pair?.0);
             }
+            names.sort();
+
+            Ok(names)
+        })
+    }
+
+    pub fn call_keyword(
+        &self,
+        plugin: &RegistryKey,
+        keyword: &str,
+        args: &str,
+        article: &serde_json::Value,
+    ) -> Result<(Option<String>, HashMap<String, String>)> {
+        self.instructions.store(0, Ordering::SeqCst);
+        self.runtime.context(|ctx| {
+            let environment: Table = ctx.registry_value(plugin)?;
+            let keywords: Table = environment.get("keywords")?;
+            let handler: Function = keywords.get(keyword)?;
 
-            ctx.globals().get::<_, String>("result")
+            match handler.call::<_, Value>((args, to_lua_value(ctx, article)?))? {
+                Value::Nil => Ok((None, HashMap::new())),
+                Value::Table(result) => Ok((
+                    result.get("content")?,
+                    result
+                        .get::<_, Option<HashMap<String, String>>>("metadata")?
+                        .unwrap_or_default(),
+                )),
+                value => Ok((Some(String::from_lua(value, ctx)?), HashMap::new())),
+            }
         })
     }
+
+    // returns None if the plugin doesn't define the hook or the hook returns nil
+    pub fn call_hook(
+        &self,
+        plugin: &RegistryKey,
+        hook: &str,
+        value: &serde_json::Value,
+    ) -> Result<Option<serde_json::Value>> {
+        self.instructions.store(0, Ordering::SeqCst);
+        self.runtime.context(|ctx| {
+            let environment: Table = ctx.registry_value(plugin)?;
+            let Some(hook) = environment.get::<_, Option<Function>>(hook)? else {
+                return Ok(None);
+            };
+
+            match hook.call::<_, Value>(to_lua_value(ctx, value)?)? {
+                Value::Nil => Ok(None),
+                value => Ok(Some(from_lua_value(value, 0)?)),
+            }
+        })
+    }
+
+    pub fn call_transform(
+        &self,
+        plugin: &RegistryKey,
+        text: String,
+        args: &HashMap<String, String>,
+        config: &serde_json::Value,
+        context: &serde_json::Value,
+    ) -> Result<String> {
+        self.instructions.store(0, Ordering::SeqCst);
+        self.runtime.context(|ctx| {
+            let environment: Table = ctx.registry_value(plugin)?;
+            let transform: Function = environment.get("transform")?;
+            let config = match config {
+                serde_json::Value::Null => Value::Table(ctx.create_table()?),
+                config => to_lua_value(ctx, config)?,
+            };
+
+            environment.set("config", config)?;
+            environment.set("result", Value::Nil)?;
+
+            let output: Option<String> =
+                transform.call((text, args.clone(), to_lua_value(ctx, context)?))?;
+
+            match output {
+                Some(output) => Ok(output),
+                None => environment
+                    .get::<_, Option<String>>("result")?
+                    .ok_or_else(|| {
+                        Error::RuntimeError(String::from(
+                            "transform didn't return a string and didn't set `result`",
+                        ))
+                    }),
+            }
+        })
+    }
+}
+
+#[test]
+fn pass_args_and_config_to_transform() {
+    let runtime = LuaRuntime::new();
+    let args: HashMap<String, String> = vec![(String::from("theme"), String::from("dark"))]
+        .into_iter()
+        .collect();
+    let config = serde_json::json!({ "width": 100, "tags": ["a", "b"] });
+
+    let plugin = runtime
+        .load(
+            "test.lua",
+            "function transform(text, args) result = text .. args.theme .. config.width .. config.tags[2] end",
+        )
+        .unwrap();
+
+    assert_eq!(
+        runtime
+            .call_transform(
+                &plugin,
+                String::from("text "),
+                &args,
+                &config,
+                &serde_json::Value::Null
+            )
+            .unwrap(),
+        "text dark100b"
+    );
+}
+
+#[test]
+fn return_transformed_text() {
+    let runtime = LuaRuntime::new();
+    let context = serde_json::json!({ "chapter": "Intro" });
+
+    let plugin = runtime
+        .load(
+            "test.lua",
+            "function transform(text, args, context) return context.chapter .. text end",
+        )
+        .unwrap();
+
+    assert_eq!(
+        runtime
+            .call_transform(
+                &plugin,
+                String::from(" text"),
+                &HashMap::new(),
+                &serde_json::Value::Null,
+                &context
+            )
+            .unwrap(),
+        "Intro text"
+    );
+}
+
+#[test]
+fn keep_plugins_isolated() {
+    let runtime = LuaRuntime::new();
+
+    let first = runtime
+        .load("first.lua", "function transform(text) return 'first' end")
+        .unwrap();
+    let second = runtime
+        .load("second.lua", "function transform(text) return 'second' end")
+        .unwrap();
+    let call = |plugin| {
+        runtime
+            .call_transform(
+                plugin,
+                String::from(""),
+                &HashMap::new(),
+                &serde_json::Value::Null,
+                &serde_json::Value::Null,
+            )
+            .unwrap()
+    };
+
+    assert_eq!(call(&first), "first");
+    assert_eq!(call(&second), "second");
+
+    let patching = runtime
+        .load(
+            "patching.lua",
+            "function transform()\n  string.upper = function() return 'patched' end\n  math.pi = 3\n  _G.shared = 'patched'\n  pcall(function() getmetatable('').__index.lower = string.upper end)\n  return 'done'\nend",
+        )
+        .unwrap();
+    let checking = runtime
+        .load(
+            "checking.lua",
+            "function transform()\n  return string.upper('ok') .. ('ok'):upper() .. ('OK'):lower() .. math.pi .. tostring(shared)\nend",
+        )
+        .unwrap();
+
+    assert_eq!(call(&patching), "done");
+    assert_eq!(call(&checking), "OKOKok3.1415926535898nil");
+}
+
+#[test]
+fn describe_lua_errors() {
+    let runtime = LuaRuntime::new();
+
+    let plugin = runtime
+        .load(
+            "plugins/test.lua",
+            "function transform(text)\n  error('broken plugin')\nend",
+        )
+        .unwrap();
+    let err = runtime
+        .call_transform(
+            &plugin,
+            String::from(""),
+            &HashMap::new(),
+            &serde_json::Value::Null,
+            &serde_json::Value::Null,
+        )
+        .unwrap_err();
+
+    assert_eq!(
+        describe_error(&err),
+        (Some(2), String::from("broken plugin"))
+    );
+
+    let err = runtime
+        .load("plugins/test.lua", "function transform(")
+        .unwrap_err();
+
+    assert_eq!(describe_error(&err).0, Some(1));
+}
+
+#[test]
+fn deny_fs_access_outside_root() {
+    let runtime = LuaRuntime::new();
+    let root = std::env::current_dir().unwrap();
+
+    runtime
+        .set_fs(lua_fs::FsSandbox::new(
+            &root,
+            &crate::parser::get_test_config(),
+        ))
+        .unwrap();
+    let plugin = runtime
+        .load(
+            "plugins/test.lua",
+            "function transform(text)\n  if fs.exists('Cargo.toml') then\n    return fs.read('../' .. text)\n  end\nend",
+        )
+        .unwrap();
+    let err = runtime
+        .call_transform(
+            &plugin,
+            String::from("secret.txt"),
+            &HashMap::new(),
+            &serde_json::Value::Null,
+            &serde_json::Value::Null,
+        )
+        .unwrap_err();
+
+    assert_eq!(
+        describe_error(&err),
+        (
+            Some(3),
+            String::from("Access to \"../secret.txt\" is denied: it's outside the project root")
+        )
+    );
+}
+
+#[test]
+fn restrict_standard_library() {
+    let runtime = LuaRuntime::new();
+
+    let plugin = runtime
+        .load(
+            "test.lua",
+            "function transform() return tostring(os) .. tostring(io) .. tostring(debug) .. tostring(loadfile) .. string.upper('ok') end",
+        )
+        .unwrap();
+
+    assert_eq!(
+        runtime
+            .call_transform(
+                &plugin,
+                String::from(""),
+                &HashMap::new(),
+                &serde_json::Value::Null,
+                &serde_json::Value::Null,
+            )
+            .unwrap(),
+        "nilnilnilnilOK"
+    );
+}
+
+#[test]
+fn limit_instructions_and_memory() {
+    let runtime = LuaRuntime::new();
+    runtime.set_limits(Some(100_000), Some(4 * 1024 * 1024));
+
+    let endless = runtime
+        .load("endless.lua", "function transform() while true do end end")
+        .unwrap();
+    let greedy = runtime
+        .load(
+            "greedy.lua",
+            "function transform() return string.rep('x', 8 * 1024 * 1024) end",
+        )
+        .unwrap();
+    let call = |plugin| {
+        runtime.call_transform(
+            plugin,
+            String::from(""),
+            &HashMap::new(),
+            &serde_json::Value::Null,
+            &serde_json::Value::Null,
+        )
+    };
+
+    assert_eq!(
+        describe_error(&call(&endless).unwrap_err()).1,
+        "the plugin exceeded the limit of 100000 instructions"
+    );
+    assert_eq!(
+        describe_error(&call(&greedy).unwrap_err()).1,
+        "memory error: not enough memory"
+    );
+}
+
+#[test]
+fn call_hooks() {
+    let runtime = LuaRuntime::new();
+    let plugin = runtime
+        .load(
+            "plugins/hooks.lua",
+            r#"
+            function on_article(article)
+              if article.topic == 'Draft' then
+                return false
+              end
+
+              article.content = '> ' .. article.content
+              article.tags = { 'a', 'b' }
+              return article
+            end
+            "#,
+        )
+        .unwrap();
+
+    assert_eq!(
+        runtime
+            .call_hook(
+                &plugin,
+                "on_article",
+                &serde_json::json!({ "topic": "Intro", "content": "text" })
+            )
+            .unwrap(),
+        Some(serde_json::json!({
+            "topic": "Intro",
+            "content": "> text",
+            "tags": ["a", "b"],
+        }))
+    );
+    assert_eq!(
+        runtime
+            .call_hook(
+                &plugin,
+                "on_article",
+                &serde_json::json!({ "topic": "Draft" })
+            )
+            .unwrap(),
+        Some(serde_json::Value::Bool(false))
+    );
+    assert_eq!(
+        runtime
+            .call_hook(&plugin, "on_finish", &serde_json::json!([]))
+            .unwrap(),
+        None
+    );
 }
@@ -1,21 +1,36 @@
 mod book;
 mod cli;
 mod config;
+mod diagrams;
+mod diff;
 mod fs_utils;
 mod generator;
 mod git;
+mod links;
+mod lua_fs;
 mod lua_runtime;
+mod native_plugin;
 mod parser;
+mod plugin_blocks;
+mod plugin_dev;
 mod plugins;
+mod snippets;
+mod stale;
+mod wasm_plugin;
 
 use ansi_term::Colour;
-use std::fs;
+use std::{fs, process};
 
-fn parse_articles(config: config::Config, root: &str) -> Vec<parser::Article> {
+fn parse_articles(
+    config: config::Config,
+    root: &str,
+    plugins: &plugins::Plugins,
+) -> Result<Vec<parser::Article>, String> {
     let mut parser = parser::Parser::new(config.clone());
-    println!("Start documentation parsing...\n");
+    parser.set_keyword_handler(plugins);
+    eprintln!("Start documentation parsing...\n");
 
-    let files_patterns: Vec<String> = vec![
+    let files_patterns: Vec<String> = [
         vec![format!("{}/**/*.fdoc.md", root)],
         config.files_patterns.clone(),
     ]
@@ -26,16 +41,168 @@ fn parse_articles(config: config::Config, root: &str) -> Vec<parser::Article> {
         paths.push(format!("{}/{}/{}", root, config.project_path, pattern));
     }
 
-    let result = parser.parse_path(paths);
+    let mut result = parser.parse_path(paths);
 
-    println!(
+    if !result.errors.is_empty() {
+        return Err(result.errors.join("\n"));
+    }
+
+    let source = links::Source::new(&config, root)?;
+
+    for article in result.articles.iter_mut() {
+        article.path = fs_utils::relative_path(&article.path, root);
+        article.link = source.as_ref().map(|source| source.create_link(article));
+        article.content = snippets::resolve_source_links(&article.content, |path, start, end| {
+            source.as_ref().map(|source| {
+                source.create_range_link(&fs_utils::relative_path(path, root), start, end)
+            })
+        });
+    }
+
+    if config.git_metadata.unwrap_or(false) {
+        git::annotate_articles(&mut result.articles, root);
+    }
+
+    eprintln!(
         "\n{} {}%",
         Colour::Green.bold().paint("Documentation coverage:"),
         result.coverage
     );
-    println!("{}", Colour::Green.bold().paint("Done!"));
+    eprintln!("{}", Colour::Green.bold().paint("Done!"));
+
+    Ok(result.articles)
+}
+
+fn exit_with_error(err: &str) -> ! {
+    git::remove_tmp_repositories();
+    git::remove_versions();
+    eprintln!("{}", err);
+    process::exit(1);
+}
+
+fn generate_documentation(config: config::Config) {
+    let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config.clone());
+    let mut articles =
+        parse_articles(config.clone(), ".", &plugins).unwrap_or_else(|err| exit_with_error(&err));
+
+    let repositories = git::clone_repositories(config.clone());
+    git::print_clone_report(&repositories);
+
+    if config.fail_on_repository_error.unwrap_or(false)
+        && repositories.iter().any(|result| result.is_err())
+    {
+        git::remove_tmp_repositories();
+        eprintln!("Cannot clone all the repositories");
+        process::exit(1);
+    }
+
+    for project in repositories.into_iter().flatten() {
+        if let Some(project_config) = project.config {
+            match parse_articles(project_config, &project.path, &plugins) {
+                Ok(mut project_articles) => articles.append(&mut project_articles),
+                Err(err) => exit_with_error(&err),
+            }
+        }
+    }
+
+    if let Err(err) = generator::generate_docs(articles, config, &plugins) {
+        exit_with_error(&err.to_string());
+    }
+}
+
+fn generate_versioned_documentation(config: config::Config, patterns: &[String]) {
+    let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config.clone());
+    let mut versions: Vec<(String, Vec<parser::Article>)> = vec![];
+    let references = git::list_versions(patterns).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    for reference in references {
+        println!("\n{} {}", Colour::Green.bold().paint("Version"), reference);
+
+        match parse_version_articles(config.clone(), &reference, &plugins) {
+            Ok(articles) => versions.push((git::version_name(&reference), articles)),
+            Err(err) => eprintln!("{}\n{} {}", err, Colour::Red.paint("Skipped"), reference),
+        }
+    }
+
+    if versions.is_empty() {
+        exit_with_error("Cannot build documentation for any of the versions");
+    }
+
+    let result = generator::generate_versioned_docs(versions, config, &plugins);
+    git::remove_versions();
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_version_articles(
+    config: config::Config,
+    reference: &str,
+    plugins: &plugins::Plugins,
+) -> Result<Vec<parser::Article>, String> {
+    let path = git::checkout_version(reference)
+        .map_err(|e| format!("Cannot checkout {}: {}", reference, e))?;
+
+    parse_articles(config, &path, plugins)
+}
+
+fn show_documentation_diff(config: config::Config, args: &clap::ArgMatches) {
+    let base = args.get_one::<String>("base").unwrap();
+    let head = args.get_one::<String>("head").unwrap();
+
+    let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config.clone());
+    let base_articles = parse_version_articles(config.clone(), base, &plugins)
+        .unwrap_or_else(|err| exit_with_error(&err));
+    let head_articles =
+        parse_version_articles(config, head, &plugins).unwrap_or_else(|err| exit_with_error(&err));
+    git::remove_versions();
+
+    let report = diff::create_report(&base_articles, &head_articles);
+
+    match args.get_one::<String>("output") {
+        Some(output) => match fs::write(output, report) {
+            Ok(_) => eprintln!("\nThe report is written to {}", output),
+            Err(e) => eprintln!("Cannot write the report: {:?}", e),
+        },
+        None => print!("{}", report),
+    }
+}
+
+fn run_plugin_command(config: config::Config, args: &clap::ArgMatches) {
+    match args.subcommand() {
+        Some(("new", new_args)) => {
+            let name = new_args.get_one::<String>("name").unwrap();
+
+            match plugin_dev::create_plugin(name, config.plugins_dir.as_deref()) {
+                Ok(files) => files
+                    .iter()
+                    .for_each(|file| println!("\"{}\" is created", file)),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(("test", _)) => {
+            let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config);
+            let results = plugin_dev::run_fixtures(&plugins);
+
+            println!("{}", plugin_dev::create_report(&results));
 
-    result.articles
+            if results
+                .iter()
+                .any(|result| result.status != plugin_dev::FixtureStatus::Passed)
+            {
+                process::exit(1);
+            }
+        }
+        _ => {}
+    }
 }
 
 fn main() {
@@ -44,29 +211,65 @@ fn main() {
     if let Some(true) = args.get_one::<bool>("init") {
         let config = config::create_default_config();
         book::init_book(config);
+    } else if let Some(diff_args) = args.subcommand_matches("diff") {
+        match config::read_config(None) {
+            Some(config) => show_documentation_diff(config, diff_args),
+            None => println!("Cannot find the config file"),
+        }
+    } else if let Some(plugin_args) = args.subcommand_matches("plugin") {
+        match config::read_config(None) {
+            Some(config) => run_plugin_command(config, plugin_args),
+            None => println!("Cannot find the config file"),
+        }
+    } else if args.subcommand_matches("stale").is_some() {
+        match config::read_config(None) {
+            Some(config) => {
+                let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config.clone());
+                let articles = parse_articles(config.clone(), ".", &plugins)
+                    .unwrap_or_else(|err| exit_with_error(&err));
+                let sections = stale::find_stale_sections(&articles, ".", &config);
+
+                print!("{}", stale::create_report(&sections));
+            }
+            None => println!("Cannot find the config file"),
+        }
     } else if let Some(true) = args.get_one::<bool>("extension") {
         if let Some(config) = config::read_config(None) {
-            let plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config);
-            if let Err(err) = plugins.run_as_plugin() {
-                eprintln!("{:#?}", err);
+            let mut plugins = plugins::Plugins::new(lua_runtime::LuaRuntime::new(), config);
+            let supports: Vec<&str> = args
+                .get_many::<String>("supports")
+                .map(|values| values.map(String::as_str).collect())
+                .unwrap_or_default();
+
+            let name = args.get_one::<String>("name").map(String::as_str);
+
+            if let ["supports", renderer] = supports.as_slice() {
+                plugins.read_book_config(name);
+                process::exit(if plugins.supports_renderer(renderer) {
+                    0
+                } else {
+                    1
+                });
+            }
+
+            if let Err(err) = plugins.run_as_plugin(name) {
+                eprintln!("{}", err);
+                process::exit(1);
             }
         }
     } else {
         match config::read_config(None) {
             Some(config) => {
-                let mut articles: Vec<parser::Article> = vec![];
                 fs_utils::recreate_dir(&config.clone().docs_folder.unwrap())
                     .expect("Cannot create the documentation folder");
-                articles.append(&mut parse_articles(config.clone(), "."));
 
-                for project in git::clone_repositories(config.clone()) {
-                    if project.config.is_some() {
-                        articles.append(&mut parse_articles(project.config.unwrap(), &project.path))
+                match &config.versions {
+                    Some(patterns) if !patterns.is_empty() => {
+                        generate_versioned_documentation(config.clone(), patterns)
                     }
+                    _ => generate_documentation(config.clone()),
                 }
 
-                generator::generate_docs(articles, config.clone());
-
                 if config.mdbook.unwrap() {
                     book::build_book();
 
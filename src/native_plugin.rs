@@ -0,0 +1,314 @@
+use libloading::{Library, Symbol};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+
+pub const API_VERSION: u32 = 1;
+
+type ApiVersionFn = unsafe extern "C" fn() -> u32;
+type TransformFn = unsafe extern "C" fn(
+    *const c_char,
+    *const c_char,
+    *const c_char,
+    *const c_char,
+    *mut *mut c_char,
+) -> c_int;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+/**
+ * @Article Plugins
+ *
+ * ## Native plugins
+ *
+ * Plugins can be written in any language which can build a shared library with the C ABI (Rust,
+ * C, Go, etc.). Put `your-plugin-name.so` (`.dylib` on macOS, `.dll` on Windows) into the plugins
+ * folder, and it will transform `{{ #your-plugin-name ... }}` blocks like Lua plugins do.
+ *
+ * A library should export three functions:
+ *
+ * ```c
+ * // returns a version of the plugin API which the plugin implements, currently it's 1
+ * uint32_t fundoc_plugin_api_version(void);
+ *
+ * // all arguments are UTF-8 strings, args, config and context are JSON objects
+ * // returns 0 and writes the result into output, or returns non-zero and writes an error message
+ * int fundoc_plugin_transform(const char *text, const char *args, const char *config,
+ *                             const char *context, char **output);
+ *
+ * // frees a string written into output
+ * void fundoc_plugin_free(char *output);
+ * ```
+ *
+ * A plugin for Rust can look like this (`crate-type = ["cdylib"]`):
+ *
+ * ```rust
+ * use std::ffi::{CStr, CString};
+ * use std::os::raw::{c_char, c_int};
+ *
+ * #[no_mangle]
+ * pub extern "C" fn fundoc_plugin_api_version() -> u32 {
+ *     1
+ * }
+ *
+ * #[no_mangle]
+ * pub unsafe extern "C" fn fundoc_plugin_transform(
+ *     text: *const c_char,
+ *     _args: *const c_char,
+ *     _config: *const c_char,
+ *     _context: *const c_char,
+ *     output: *mut *mut c_char,
+ * ) -> c_int {
+ *     let text = CStr::from_ptr(text).to_string_lossy();
+ *     *output = CString::new(format!("<b>{}</b>", text)).unwrap().into_raw();
+ *     0
+ * }
+ *
+ * #[no_mangle]
+ * pub unsafe extern "C" fn fundoc_plugin_free(output: *mut c_char) {
+ *     drop(CString::from_raw(output));
+ * }
+ * ```
+ *
+ * > **NOTE** native plugins aren't sandboxed, so they are loaded only if `native_plugins` is
+ * > enabled in the config file. Use them only if you trust them.
+ */
+pub struct NativePlugin {
+    library: Result<Library, String>,
+}
+
+fn check_version(library: &Library) -> Result<(), String> {
+    let version = unsafe {
+        let api_version: Symbol<ApiVersionFn> = library
+            .get(b"fundoc_plugin_api_version\0")
+            .map_err(|e| e.to_string())?;
+        api_version()
+    };
+
+    if version != API_VERSION {
+        return Err(format!(
+            "the plugin implements API version {}, but Fundoc supports version {}",
+            version, API_VERSION
+        ));
+    }
+
+    unsafe {
+        library
+            .get::<TransformFn>(b"fundoc_plugin_transform\0")
+            .map_err(|e| e.to_string())?;
+        library
+            .get::<FreeFn>(b"fundoc_plugin_free\0")
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn to_c_string(value: &str) -> Result<CString, String> {
+    CString::new(value).map_err(|e| e.to_string())
+}
+
+impl NativePlugin {
+    pub fn load(file: &str) -> Self {
+        let library = unsafe { Library::new(file) }
+            .map_err(|e| e.to_string())
+            .and_then(|library| check_version(&library).map(|_| library));
+
+        Self { library }
+    }
+
+    pub fn transform(
+        &self,
+        text: &str,
+        args: &HashMap<String, String>,
+        config: &serde_json::Value,
+        context: &serde_json::Value,
+    ) -> Result<String, String> {
+        let library = self.library.as_ref().map_err(String::clone)?;
+        let text = to_c_string(text)?;
+        let args = to_c_string(&serde_json::json!(args).to_string())?;
+        let config = to_c_string(&config.to_string())?;
+        let context = to_c_string(&context.to_string())?;
+        let mut output: *mut c_char = std::ptr::null_mut();
+
+        unsafe {
+            let transform: Symbol<TransformFn> = library
+                .get(b"fundoc_plugin_transform\0")
+                .map_err(|e| e.to_string())?;
+            let free: Symbol<FreeFn> = library
+                .get(b"fundoc_plugin_free\0")
+                .map_err(|e| e.to_string())?;
+
+            let status = transform(
+                text.as_ptr(),
+                args.as_ptr(),
+                config.as_ptr(),
+                context.as_ptr(),
+                &mut output,
+            );
+
+            let message = if output.is_null() {
+                String::from("")
+            } else {
+                let message = CStr::from_ptr(output).to_string_lossy().to_string();
+                free(output);
+                message
+            };
+
+            match status {
+                0 => Ok(message),
+                status if message.is_empty() => {
+                    Err(format!("the plugin failed with code {}", status))
+                }
+                _ => Err(message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn build_test_library(name: &str, source: &str) -> String {
+    let dir = std::env::temp_dir().join("fundoc_native_plugins");
+    let source_file = dir.join(format!("{}.rs", name));
+    let library_file = dir.join(format!("{}.{}", name, std::env::consts::DLL_EXTENSION));
+
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&source_file, source).unwrap();
+
+    let status = std::process::Command::new(
+        std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc")),
+    )
+    .args(["--crate-type", "cdylib", "--edition", "2018", "-o"])
+    .arg(&library_file)
+    .arg(&source_file)
+    .status()
+    .unwrap();
+    assert!(status.success());
+
+    library_file.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+const TEST_PLUGIN: &str = r#"
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FREED: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+pub extern "C" fn fundoc_plugin_api_version() -> u32 {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fundoc_plugin_transform(
+    text: *const c_char,
+    args: *const c_char,
+    _config: *const c_char,
+    _context: *const c_char,
+    output: *mut *mut c_char,
+) -> c_int {
+    let text = CStr::from_ptr(text).to_string_lossy();
+    let args = CStr::from_ptr(args).to_string_lossy();
+
+    if text == "fail" {
+        *output = CString::new("broken plugin").unwrap().into_raw();
+        return 1;
+    }
+
+    *output = CString::new(format!("<b>{}</b> {}", text, args)).unwrap().into_raw();
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fundoc_plugin_free(output: *mut c_char) {
+    FREED.fetch_add(1, Ordering::SeqCst);
+    drop(CString::from_raw(output));
+}
+
+#[no_mangle]
+pub extern "C" fn fundoc_test_freed() -> usize {
+    FREED.load(Ordering::SeqCst)
+}
+"#;
+
+#[test]
+fn transform_text_with_native_plugins() {
+    let file = build_test_library(
+        "bold",
+        &format!("const API_VERSION: u32 = {};\n{}", API_VERSION, TEST_PLUGIN),
+    );
+    let plugin = NativePlugin::load(&file);
+    let args: HashMap<String, String> = vec![(String::from("class"), String::from("note"))]
+        .into_iter()
+        .collect();
+    let transform = |text: &str| {
+        plugin.transform(
+            text,
+            &args,
+            &serde_json::Value::Null,
+            &serde_json::Value::Null,
+        )
+    };
+
+    assert_eq!(
+        transform("text"),
+        Ok(String::from("<b>text</b> {\"class\":\"note\"}"))
+    );
+    assert_eq!(transform("fail"), Err(String::from("broken plugin")));
+
+    let freed = unsafe {
+        let library = plugin.library.as_ref().unwrap();
+        let freed: Symbol<unsafe extern "C" fn() -> usize> =
+            library.get(b"fundoc_test_freed\0").unwrap();
+        freed()
+    };
+    assert_eq!(freed, 2);
+}
+
+#[test]
+fn report_invalid_libraries() {
+    let plugin = NativePlugin::load("./plugins/missing.so");
+
+    assert!(plugin
+        .transform(
+            "text",
+            &HashMap::new(),
+            &serde_json::Value::Null,
+            &serde_json::Value::Null
+        )
+        .is_err());
+
+    let outdated = build_test_library(
+        "outdated",
+        &format!(
+            "const API_VERSION: u32 = {};\n{}",
+            API_VERSION + 1,
+            TEST_PLUGIN
+        ),
+    );
+    let without_api = build_test_library(
+        "without_api",
+        "#[no_mangle]\npub extern \"C\" fn another_function() -> u32 {\n    1\n}\n",
+    );
+    let transform = |file: &str| {
+        NativePlugin::load(file)
+            .transform(
+                "text",
+                &HashMap::new(),
+                &serde_json::Value::Null,
+                &serde_json::Value::Null,
+            )
+            .unwrap_err()
+    };
+
+    assert_eq!(
+        transform(&outdated),
+        format!(
+            "the plugin implements API version {}, but Fundoc supports version {}",
+            API_VERSION + 1,
+            API_VERSION
+        )
+    );
+    assert!(transform(&without_api).contains("fundoc_plugin_api_version"));
+}
@@ -1,9 +1,15 @@
 use glob::glob;
 use regex::Regex;
+use std::collections::BTreeMap;
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use crate::config;
+use crate::fs_utils;
+use crate::git;
+use crate::snippets;
 
 #[derive(Debug, PartialEq, Eq)]
 enum ParserState {
@@ -112,11 +118,32 @@ pub struct Article {
     pub path: String,
     pub start_line: i16,
     pub end_line: i16,
+    pub code_line: Option<i16>,
+    pub last_change: Option<git::Commit>,
+    pub link: Option<String>,
+    pub metadata: BTreeMap<String, String>,
 }
 
 pub struct ParsingResult {
     pub articles: Vec<Article>,
     pub coverage: f32,
+    pub errors: Vec<String>,
+}
+
+/**
+ * @Article Syntax
+ *
+ * Plugins can add their own keywords. For example, a plugin can turn `@Jira ABC-123` into a link to
+ * the issue. Read more about it in the [Plugins](./plugins.md) article.
+ */
+pub trait KeywordHandler {
+    fn keywords(&self) -> Result<Vec<String>, String>;
+    fn handle_keyword(
+        &self,
+        keyword: &str,
+        args: &str,
+        article: &mut Article,
+    ) -> Result<(), String>;
 }
 
 impl PartialEq for Article {
@@ -126,6 +153,10 @@ impl PartialEq for Article {
             && self.path == other.path
             && self.start_line == other.start_line
             && self.end_line == other.end_line
+            && self.code_line == other.code_line
+            && self.last_change == other.last_change
+            && self.link == other.link
+            && self.metadata == other.metadata
     }
 }
 
@@ -149,6 +180,7 @@ enum Keyword {
      * fn main() {}
      * ```
      */
+    #[allow(clippy::needless_doctest_main)]
     Article,
     /**
     * @Article Syntax
@@ -219,6 +251,24 @@ enum Keyword {
      */
     CodeBlockStart,
     CodeBlockEnd,
+    /**
+     * @Article Syntax
+     * `@Include <path>#<region> <Programming Language>` includes code from any file of the project
+     * as a fenced code block with a link to its source. A path is relative to the current file, and
+     * the language is detected by the file extension if it isn't passed. For example,
+     * `@Include ../examples/users.rs#create-user rust` includes the `create-user` region of the
+     * `examples/users.rs` file. Absolute paths and paths outside the project root aren't allowed.
+     */
+    Include,
+    /**
+     * @Article Syntax
+     * `@Embed <path> <kind> <name> <Programming Language>` embeds the whole declaration of a
+     * symbol, so a code sample always matches the real code. The path can be omitted to embed a
+     * symbol from the current file. For example, `@Embed fn calculate_total` embeds the
+     * `calculate_total` function, and `@Embed ../models/invoice.rs struct Invoice` embeds the
+     * `Invoice` struct from another file. The path follows the same rules as in `@Include`.
+     */
+    Embed,
     /**
      * @Article Syntax
      * `@Ignore` is for ignoring a marked documentation section.
@@ -234,11 +284,13 @@ impl Keyword {
             Keyword::Ignore => "@Ignore",
             Keyword::CodeBlockStart => "@CodeBlockStart",
             Keyword::CodeBlockEnd => "@CodeBlockEnd",
+            Keyword::Include => "@Include",
+            Keyword::Embed => "@Embed",
         }
     }
 }
 
-pub struct Parser {
+pub struct Parser<'a> {
     state_machine: ParserStateMachine,
     comment_symbol: char,
     start_comment: String,
@@ -248,9 +300,14 @@ pub struct Parser {
 
     articles: Vec<Article>,
     current_article: Article,
+
+    keyword_handler: Option<&'a dyn KeywordHandler>,
+    custom_keywords: Vec<String>,
+    errors: Vec<String>,
+    root: PathBuf,
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
     pub fn new(config: config::Config) -> Self {
         let start_comment = config
             .comment_start_string
@@ -267,6 +324,10 @@ impl Parser {
             path: String::from(""),
             start_line: 1,
             end_line: 1,
+            code_line: None,
+            last_change: None,
+            link: None,
+            metadata: BTreeMap::new(),
         };
 
         Self {
@@ -278,6 +339,119 @@ impl Parser {
             end_comment,
             articles,
             current_article,
+            keyword_handler: None,
+            custom_keywords: vec![],
+            errors: vec![],
+            root: env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    pub fn set_keyword_handler(&mut self, keyword_handler: &'a dyn KeywordHandler) {
+        match keyword_handler.keywords() {
+            Ok(keywords) => {
+                self.custom_keywords = keywords
+                    .iter()
+                    .map(|keyword| format!("@{}", keyword))
+                    .collect()
+            }
+            Err(err) => self.errors.push(err),
+        }
+        self.keyword_handler = Some(keyword_handler);
+    }
+
+    fn find_custom_keyword(&self, line: &str) -> Option<String> {
+        let keyword = line.split_whitespace().next()?;
+
+        self.custom_keywords
+            .iter()
+            .find(|custom_keyword| *custom_keyword == keyword)
+            .cloned()
+    }
+
+    fn handle_custom_keyword(&mut self, keyword: &str, line: &str, line_number: i16) {
+        let Some(keyword_handler) = self.keyword_handler else {
+            return;
+        };
+        let args = line[keyword.len()..].trim();
+
+        if let Err(err) =
+            keyword_handler.handle_keyword(&keyword[1..], args, &mut self.current_article)
+        {
+            self.errors.push(format!(
+                "{}:{}: {}: {}",
+                self.current_article.path, line_number, keyword, err
+            ));
+        }
+    }
+
+    fn read_snippet(&self, keyword: &Keyword, args: &str) -> Result<String, String> {
+        let current_file = Path::new(&self.current_article.path);
+        let current_dir = current_file.parent().unwrap_or_else(|| Path::new(""));
+        let root = fs_utils::normalize_path(&self.root.to_string_lossy());
+        let resolve_path = |path: &str| {
+            if Path::new(path).is_absolute() {
+                return Err(format!(
+                    "\"{}\" is an absolute path: use a path relative to the current file",
+                    path
+                ));
+            }
+
+            let resolved = fs_utils::normalize_path(&current_dir.join(path).to_string_lossy());
+
+            if !fs_utils::normalize_path(&root.join(&resolved).to_string_lossy()).starts_with(&root)
+            {
+                return Err(format!("\"{}\" is outside the project root", path));
+            }
+
+            Ok(resolved)
+        };
+
+        let (path, snippet, lang) = match keyword {
+            Keyword::Embed => {
+                let embed = snippets::parse_embed(args)?;
+                let path = embed
+                    .path
+                    .as_deref()
+                    .map(resolve_path)
+                    .transpose()?
+                    .unwrap_or_else(|| current_file.to_path_buf());
+                let snippet = snippets::find_symbol(&path, &embed.kind, &embed.name)?;
+
+                (path, snippet, embed.lang)
+            }
+            _ => {
+                let include = snippets::parse_include(args)?;
+                let path = resolve_path(&include.path)?;
+                let snippet = snippets::read_snippet(&path, include.selector.as_deref())?;
+
+                (path, snippet, include.lang)
+            }
+        };
+        let lang = lang.unwrap_or_else(|| {
+            path.extension()
+                .map(|extension| extension.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        Ok(snippets::format_snippet(
+            &snippet,
+            &lang,
+            &path.to_string_lossy(),
+        ))
+    }
+
+    fn insert_snippet(&mut self, keyword: Keyword, line: &str, line_number: i16) {
+        let args = line[keyword.as_str().len()..].trim();
+
+        match self.read_snippet(&keyword, args) {
+            Ok(content) => self.current_article.content += &content,
+            Err(err) => self.errors.push(format!(
+                "{}:{}: {}: {}",
+                self.current_article.path,
+                line_number,
+                keyword.as_str(),
+                err
+            )),
         }
     }
 
@@ -338,14 +512,18 @@ impl Parser {
         Article {
             topic: String::from(""),
             content: String::from(""),
-            path: String::from(""),
+            path: self.current_article.path.clone(),
             start_line: 1,
             end_line: 1,
+            code_line: None,
+            last_change: None,
+            link: None,
+            metadata: BTreeMap::new(),
         }
     }
 
     fn parse_fdoc_file(&self, file_content: &str, file_path: &str) -> Vec<Article> {
-        let file_name = file_path.split('/').last().unwrap();
+        let file_name = file_path.split('/').next_back().unwrap();
         let name_chunks: Vec<&str> = file_name.rsplit('.').collect();
         let topic = name_chunks[2..].join(".");
 
@@ -355,10 +533,14 @@ impl Parser {
             path: String::from(file_path),
             start_line: 1,
             end_line: 1,
+            code_line: None,
+            last_change: None,
+            link: None,
+            metadata: BTreeMap::new(),
         }]
     }
 
-    fn parse_text<'a>(&self, line: &'a str) -> &'a str {
+    fn parse_text<'l>(&self, line: &'l str) -> &'l str {
         let empty_comment_line = format!("{} ", self.comment_symbol);
         let trimmed_line = line.trim_start();
 
@@ -398,10 +580,18 @@ impl Parser {
         };
     }
 
+    // code of a code block starts right after the comment with `@CodeBlockStart`
+    fn set_code_block_start(&mut self, line: &str, line_number: i16) {
+        if self.current_article.code_line.is_none() && line.trim().ends_with(&self.end_comment) {
+            self.current_article.code_line = Some(line_number + 1);
+        }
+    }
+
     fn complete_article_parsing(&mut self, line_number: i16) {
         if !self.current_article.topic.is_empty() {
             self.current_article.content = self.current_article.content.trim().to_string();
             self.current_article.end_line = line_number - 1;
+            self.current_article.code_line = Some(line_number + 1);
             self.articles.push(self.current_article.clone());
 
             self.current_article = self.new_article();
@@ -439,6 +629,7 @@ impl Parser {
 
             self.current_article.content += format!("```{}", self.code_block).as_str();
             self.state_machine.to_code_block_mut();
+            self.set_code_block_start(line, line_number);
         } else if self.state_machine.is_in(ParserState::CodeBlockParsing)
             && (trimmed_line.starts_with(self.start_comment.as_str())
                 || trimmed_line.starts_with(Keyword::CodeBlockEnd.as_str()))
@@ -451,15 +642,32 @@ impl Parser {
 
             self.current_article = self.new_article();
             self.state_machine.to_skippintg_mut();
+        } else if trimmed_line.starts_with(Keyword::Include.as_str())
+            && self.state_machine.is_in(ParserState::ArticleParsing)
+        {
+            self.insert_snippet(Keyword::Include, &trimmed_line, line_number);
+        } else if trimmed_line.starts_with(Keyword::Embed.as_str())
+            && self.state_machine.is_in(ParserState::ArticleParsing)
+        {
+            self.insert_snippet(Keyword::Embed, &trimmed_line, line_number);
+        } else if let Some(keyword) = self
+            .find_custom_keyword(&trimmed_line)
+            .filter(|_| self.state_machine.is_in(ParserState::ArticleParsing))
+        {
+            self.handle_custom_keyword(&keyword, &trimmed_line, line_number);
         } else if self.state_machine.is_in(ParserState::ArticleParsing)
             || self.state_machine.is_in(ParserState::CodeBlockParsing)
             || self.state_machine.is_in(ParserState::NestedCommentParsing)
         {
+            if self.state_machine.is_in(ParserState::CodeBlockParsing) {
+                self.set_code_block_start(line, line_number);
+            }
+
             self.current_article.content += &format!("{}\n", self.parse_text(line));
         }
     }
 
-    fn parse_file(&mut self, file_content: &str, file_path: &str) -> Vec<Article> {
+    pub fn parse_file(&mut self, file_content: &str, file_path: &str) -> Vec<Article> {
         self.articles = vec![];
 
         if file_path.ends_with(".fdoc.md") {
@@ -468,11 +676,11 @@ impl Parser {
 
         self.current_article.path = file_path.to_string();
 
-        let mut line_number = 1;
-
         self.state_machine.to_skippintg_mut();
 
-        for line in file_content.lines() {
+        for (line_index, line) in file_content.lines().enumerate() {
+            let line_number = line_index as i16 + 1;
+
             self.set_comment_boundaries(line);
 
             if self.state_machine.is_in(ParserState::ArticleEnding) {
@@ -480,8 +688,6 @@ impl Parser {
             } else if !self.state_machine.is_in(ParserState::Skipping) {
                 self.parse_article_content(line, line_number);
             }
-
-            line_number += 1;
         }
 
         self.articles.clone()
@@ -514,7 +720,7 @@ impl Parser {
                         result.append(articles);
                     }
                     Err(e) => {
-                        println!("{:?}", e);
+                        eprintln!("{:?}", e);
                     }
                 }
             }
@@ -523,18 +729,40 @@ impl Parser {
         ParsingResult {
             articles: result,
             coverage: files_with_documentation / files_counter * 100.0,
+            errors: self.errors.clone(),
         }
     }
 }
 
 // fundoc-disable
 #[cfg(test)]
-fn get_test_config() -> config::Config {
+pub fn get_test_article(topic: &str, content: &str, path: &str) -> Article {
+    Article {
+        topic: String::from(topic),
+        content: String::from(content),
+        path: String::from(path),
+        start_line: 1,
+        end_line: 2,
+        code_line: None,
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+pub fn get_test_config() -> config::Config {
     config::Config {
         project_path: "test".to_string(),
         files_patterns: vec!["test".to_string()],
         docs_folder: None,
         repository_host: None,
+        permalinks: None,
+        source_link_template: None,
+        versions: None,
+        git_metadata: None,
+        stale_code_lines: None,
+        stale_threshold_days: None,
         comment_start_string: None,
         comment_prefix: None,
         comment_end_string: None,
@@ -542,7 +770,16 @@ fn get_test_config() -> config::Config {
         book_name: None,
         book_build_dir: None,
         repositories: None,
+        clone_concurrency: None,
+        fail_on_repository_error: None,
         plugins_dir: None,
+        plugins: None,
+        plugins_fs_write: None,
+        plugins_fs_outside_root: None,
+        plugins_instruction_limit: None,
+        plugins_memory_limit_mb: None,
+        native_plugins: None,
+        diagrams_cache_dir: None,
     }
 }
 
@@ -564,11 +801,34 @@ pub fn test () {}
         path: "".to_string(),
         start_line: 3,
         end_line: 4,
+        code_line: Some(6),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
 }
 
+#[test]
+fn keep_file_path_for_all_articles_of_file() {
+    let mut parser = Parser::new(get_test_config());
+    let file_content = "
+/**
+ * @Article First article
+ */
+
+/**
+ * @Article Second article
+ */
+  ";
+
+    let articles = parser.parse_file(file_content, "src/test.rs");
+
+    assert_eq!(articles[0].path, "src/test.rs");
+    assert_eq!(articles[1].path, "src/test.rs");
+}
+
 #[test]
 fn ignore_comments_with_ignore_mark() {
     let mut parser = Parser::new(get_test_config());
@@ -609,6 +869,10 @@ pub fn test () {}
         path: "".to_string(),
         start_line: 5,
         end_line: 7,
+        code_line: Some(9),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -655,6 +919,10 @@ pub fn test () {}
         path: "".to_string(),
         start_line: 5,
         end_line: 16,
+        code_line: Some(18),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -689,6 +957,10 @@ fn parse_documentation_with_indentation_before_comments() {
         path: "".to_string(),
         start_line: 3,
         end_line: 16,
+        code_line: Some(18),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -719,6 +991,10 @@ pub fn test () {}
         path: "".to_string(),
         start_line: 5,
         end_line: 11,
+        code_line: Some(13),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -743,6 +1019,10 @@ use std::io::prelude::*;
         path: "".to_string(),
         start_line: 5,
         end_line: 6,
+        code_line: Some(8),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -765,6 +1045,10 @@ test
         path: "".to_string(),
         start_line: 3,
         end_line: 4,
+        code_line: Some(6),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -789,6 +1073,10 @@ const b = 2
         path: "".to_string(),
         start_line: 3,
         end_line: 4,
+        code_line: Some(6),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -821,6 +1109,10 @@ fn use_global_article_attribute() {
             path: "".to_string(),
             start_line: 6,
             end_line: 7,
+            code_line: Some(9),
+            last_change: None,
+            link: None,
+            metadata: BTreeMap::new(),
         },
         Article {
             topic: String::from("Test article"),
@@ -828,6 +1120,10 @@ fn use_global_article_attribute() {
             path: "".to_string(),
             start_line: 11,
             end_line: 12,
+            code_line: Some(14),
+            last_change: None,
+            link: None,
+            metadata: BTreeMap::new(),
         },
     ];
 
@@ -861,6 +1157,10 @@ fn ignore_sections_in_case_of_global_article() {
         path: "".to_string(),
         start_line: 6,
         end_line: 7,
+        code_line: Some(9),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -886,6 +1186,10 @@ const TIMEOUT = 3000
         path: "".to_string(),
         start_line: 3,
         end_line: 7,
+        code_line: Some(7),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -913,6 +1217,10 @@ const TIMEOUT = 3000
         path: "".to_string(),
         start_line: 3,
         end_line: 7,
+        code_line: Some(7),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -938,6 +1246,10 @@ fn parse_code_block_attribute_from_ending_comment_only() {
         path: "".to_string(),
         start_line: 3,
         end_line: 7,
+        code_line: Some(7),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -965,6 +1277,10 @@ fn parse_nested_commends() {
         path: "".to_string(),
         start_line: 3,
         end_line: 9,
+        code_line: Some(11),
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(articles, expected_result);
@@ -1003,12 +1319,17 @@ fn parse_fdoc_file_check() {
         path: "/some/long/path/to/file.fdoc.md".to_string(),
         start_line: 1,
         end_line: 1,
+        code_line: None,
+        last_change: None,
+        link: None,
+        metadata: BTreeMap::new(),
     }];
 
     assert_eq!(result, expected_result);
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn check_state_machine_state_comparator() {
     let mut state_machine = ParserStateMachine::new();
     state_machine.to_comment_section_mut();
@@ -1016,3 +1337,185 @@ fn check_state_machine_state_comparator() {
     assert_eq!(state_machine.is_in(ParserState::CommentParsing), true);
     assert_eq!(state_machine.is_in(ParserState::Skipping), false);
 }
+
+#[cfg(test)]
+struct TestKeywordHandler;
+
+#[cfg(test)]
+impl KeywordHandler for TestKeywordHandler {
+    fn keywords(&self) -> Result<Vec<String>, String> {
+        Ok(vec![String::from("Jira"), String::from("Article")])
+    }
+
+    fn handle_keyword(
+        &self,
+        keyword: &str,
+        args: &str,
+        article: &mut Article,
+    ) -> Result<(), String> {
+        if args.is_empty() {
+            return Err(String::from("an issue is required"));
+        }
+
+        article.content += &format!("{} {}\n", keyword, args);
+        article
+            .metadata
+            .insert(String::from("issue"), args.to_string());
+
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_custom_keywords() {
+    let handler = TestKeywordHandler;
+    let mut parser = Parser::new(get_test_config());
+    parser.set_keyword_handler(&handler);
+    let file_content = "/**
+ * @Article Test article
+ * @Jira ABC-123
+ * @Jira
+ * @Jirafe text
+ */
+";
+
+    let articles = parser.parse_file(file_content, "src/main.rs");
+
+    assert_eq!(articles[0].topic, "Test article");
+    assert_eq!(articles[0].content, "Jira ABC-123\n@Jirafe text");
+    assert_eq!(articles[0].metadata["issue"], "ABC-123");
+    assert_eq!(
+        parser.errors,
+        vec!["src/main.rs:4: @Jira: an issue is required"]
+    );
+}
+
+#[test]
+fn include_code_from_other_files() {
+    let dir = std::env::temp_dir().join("fundoc_parser_include");
+    std::fs::create_dir_all(dir.join("examples")).unwrap();
+    std::fs::write(
+        dir.join("examples/users.rs"),
+        "fn main() {\n    // fundoc-region: create\n    let user = User::new();\n    // fundoc-endregion\n}\n",
+    )
+    .unwrap();
+    let file_path = dir.join("src/main.rs").to_string_lossy().to_string();
+
+    let mut parser = Parser::new(get_test_config());
+    parser.root = dir.clone();
+    let file_content = "/**
+ * @Article Test article
+ * Create a user:
+ * @Include ../examples/users.rs#create
+ * @Include ../examples/missing.rs
+ * @Include /etc/hosts
+ * @Include ../../outside.rs
+ */
+";
+
+    let articles = parser.parse_file(file_content, &file_path);
+    let included_path = dir.join("examples/users.rs").to_string_lossy().to_string();
+
+    assert_eq!(
+        articles[0].content,
+        format!(
+            "Create a user:\n```rs\nlet user = User::new();\n```\n[[~]](fundoc-source://3-3/{})",
+            included_path
+        )
+    );
+    assert_eq!(parser.errors.len(), 3);
+    assert!(parser.errors[0].starts_with(&format!("{}:5: @Include: cannot read", file_path)));
+    assert_eq!(
+        parser.errors[1],
+        format!(
+            "{}:6: @Include: \"/etc/hosts\" is an absolute path: use a path relative to the current file",
+            file_path
+        )
+    );
+    assert_eq!(
+        parser.errors[2],
+        format!(
+            "{}:7: @Include: \"../../outside.rs\" is outside the project root",
+            file_path
+        )
+    );
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn embed_symbols_by_name() {
+    let dir = std::env::temp_dir().join("fundoc_parser_embed");
+    std::fs::create_dir_all(dir.join("models")).unwrap();
+    std::fs::write(
+        dir.join("models/invoice.rs"),
+        "pub struct Invoice {\n    pub total: u32,\n}\n",
+    )
+    .unwrap();
+    let file_path = dir.join("src/billing.rs").to_string_lossy().to_string();
+    let file_content = "/**
+ * @Article Billing
+ * @Embed ../models/invoice.rs struct Invoice
+ * @Embed fn calculate_total
+ * @Embed fn missing
+ * @Embed /etc/hosts fn main
+ * @Embed ../../outside.rs fn main
+ */
+fn calculate_total(items: &[u32]) -> u32 {
+    items.iter().sum()
+}
+";
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(&file_path, file_content).unwrap();
+
+    let mut parser = Parser::new(get_test_config());
+    parser.root = dir.clone();
+    let articles = parser.parse_file(file_content, &file_path);
+    let invoice_path = dir.join("models/invoice.rs").to_string_lossy().to_string();
+
+    assert_eq!(
+        articles[0].content,
+        format!(
+            "```rs\npub struct Invoice {{\n    pub total: u32,\n}}\n```\n[[~]](fundoc-source://1-3/{})\n```rs\nfn calculate_total(items: &[u32]) -> u32 {{\n    items.iter().sum()\n}}\n```\n[[~]](fundoc-source://9-11/{})",
+            invoice_path, file_path
+        )
+    );
+    assert_eq!(
+        parser.errors,
+        vec![
+            format!(
+                "{}:5: @Embed: cannot find `fn missing` in \"{}\"",
+                file_path, file_path
+            ),
+            format!(
+                "{}:6: @Embed: \"/etc/hosts\" is an absolute path: use a path relative to the current file",
+                file_path
+            ),
+            format!(
+                "{}:7: @Embed: \"../../outside.rs\" is outside the project root",
+                file_path
+            ),
+        ]
+    );
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn keep_test_fixtures_out_of_own_documentation() {
+    let config = config::read_config(None).unwrap();
+    let mut parser = Parser::new(config.clone());
+    let paths = config
+        .files_patterns
+        .iter()
+        .map(|pattern| format!("./{}/{}", config.project_path, pattern))
+        .collect();
+
+    let result = parser.parse_path(paths);
+
+    assert!(result.errors.is_empty());
+    assert!(!result
+        .articles
+        .iter()
+        .any(|article| article.topic == "Test article"));
+}
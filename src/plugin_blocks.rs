@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub args: HashMap<String, String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnclosedBlock {
+    pub line: usize,
+}
+
+fn get_line_number(content: &str, position: usize) -> usize {
+    content[..position].matches('\n').count() + 1
+}
+
+fn is_block_start(content: &str, position: usize, header: &str) -> bool {
+    let is_escaped = content[..position].ends_with('\\');
+    let rest = &content[position + header.len()..];
+    let is_full_name =
+        rest.is_empty() || rest.starts_with("}}") || rest.starts_with(|c: char| c.is_whitespace());
+
+    !is_escaped && is_full_name
+}
+
+fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut value = String::from("");
+
+    if chars.peek() != Some(&'"') {
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            value.push(c);
+        }
+
+        return Some(value);
+    }
+
+    chars.next();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => value.push(chars.next()?),
+            c => value.push(c),
+        }
+    }
+}
+
+/**
+ * @Article Plugins
+ *
+ * A block can have arguments. They're placed on the first line of the block right after the name of
+ * the plugin, and the rest of the block is the text for the plugin:
+ *
+ * ```
+ * {{ #your-plugin-name theme=dark title="Some title"
+ *  any text here
+ * }}
+ *
+ * ```
+ *
+ * Values with spaces should be quoted. If the first line contains anything except `key=value`
+ * pairs, it's considered a part of the text.
+ */
+fn parse_args(line: &str) -> Option<HashMap<String, String>> {
+    let mut args = HashMap::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut key = String::from("");
+
+        while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '-') {
+            key.push(c);
+        }
+
+        if key.is_empty() || chars.next() != Some('=') {
+            return None;
+        }
+
+        args.insert(key, parse_value(&mut chars)?);
+
+        if chars.next_if(|c| c.is_whitespace()).is_none() && chars.peek().is_some() {
+            return None;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    Some(args)
+}
+
+fn split_args(text: &str) -> (HashMap<String, String>, String) {
+    let (line, body) = text.split_once('\n').unwrap_or((text, ""));
+
+    match parse_args(line) {
+        Some(args) if !args.is_empty() => (args, body.trim().to_string()),
+        _ => (HashMap::new(), text.trim().to_string()),
+    }
+}
+
+/**
+ * @Article Plugins
+ *
+ * Blocks can contain braces, for example `{{ #your-plugin-name a {{ b }} c }}` passes `a {{ b }} c`
+ * to the plugin. Unpaired delimiters inside a block should be escaped with a backslash: `\}}` and
+ * `\{{`. A block which isn't closed fails the build with a name of the chapter and a line of the
+ * block.
+ */
+pub fn find_blocks(content: &str, name: &str) -> Result<Vec<Block>, UnclosedBlock> {
+    let header = format!("{{{{ #{}", name);
+    let mut blocks: Vec<Block> = vec![];
+    let mut position = 0;
+
+    while let Some(offset) = content[position..].find(&header) {
+        let start = position + offset;
+
+        if !is_block_start(content, start, &header) {
+            position = start + header.len();
+            continue;
+        }
+
+        let mut cursor = start + header.len();
+        let mut depth = 1;
+        let mut text = String::from("");
+
+        while depth > 0 {
+            let rest = &content[cursor..];
+
+            if rest.is_empty() {
+                return Err(UnclosedBlock {
+                    line: get_line_number(content, start),
+                });
+            } else if rest.starts_with("\\{{") || rest.starts_with("\\}}") {
+                text += &rest[1..3];
+                cursor += 3;
+            } else if rest.starts_with("{{") {
+                depth += 1;
+                text += "{{";
+                cursor += 2;
+            } else if rest.starts_with("}}") {
+                depth -= 1;
+                if depth > 0 {
+                    text += "}}";
+                }
+                cursor += 2;
+            } else {
+                let c = rest.chars().next().unwrap();
+                text.push(c);
+                cursor += c.len_utf8();
+            }
+        }
+
+        let (args, text) = split_args(&text);
+
+        blocks.push(Block {
+            start,
+            end: cursor,
+            text,
+            args,
+        });
+        position = cursor;
+    }
+
+    Ok(blocks)
+}
+
+#[test]
+fn find_multiple_blocks() {
+    let content = "{{ #mermaid A }}\ntext between\n{{ #mermaid\nB\n}}";
+
+    let blocks = find_blocks(content, "mermaid").unwrap();
+
+    assert_eq!(
+        blocks,
+        vec![
+            Block {
+                start: 0,
+                end: 16,
+                text: String::from("A"),
+                args: HashMap::new(),
+            },
+            Block {
+                start: 30,
+                end: 46,
+                text: String::from("B"),
+                args: HashMap::new(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn find_blocks_with_braces_inside() {
+    let content = "{{ #tpl a {{ b }} c \\}} d }}";
+
+    let blocks = find_blocks(content, "tpl").unwrap();
+
+    assert_eq!(blocks[0].text, "a {{ b }} c }} d");
+    assert_eq!(blocks[0].end, content.len());
+}
+
+#[test]
+fn skip_escaped_and_other_blocks() {
+    let content = "\\{{ #mermaid A }} {{ #mermaid-v2 B }} {{ #mermaid}}";
+
+    let blocks = find_blocks(content, "mermaid").unwrap();
+
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].text, "");
+}
+
+#[test]
+fn report_unclosed_blocks() {
+    let content = "text\n{{ #mermaid A }}\n{{ #mermaid B\n";
+
+    assert_eq!(
+        find_blocks(content, "mermaid"),
+        Err(UnclosedBlock { line: 3 })
+    );
+}
+
+#[test]
+fn find_blocks_with_args() {
+    let content = "{{ #chart theme=dark title=\"x \\\"y\\\" z\"\n  a=b\n}} {{ #chart width=100 }}";
+
+    let blocks = find_blocks(content, "chart").unwrap();
+
+    assert_eq!(blocks[0].text, "a=b");
+    assert_eq!(blocks[0].args["theme"], "dark");
+    assert_eq!(blocks[0].args["title"], "x \"y\" z");
+    assert_eq!(blocks[1].text, "");
+    assert_eq!(blocks[1].args["width"], "100");
+}
+
+#[test]
+fn keep_text_without_args() {
+    let content = "{{ #chart a -> b\n c=d }} {{ #chart\nkey=value\n}}";
+
+    let blocks = find_blocks(content, "chart").unwrap();
+
+    assert_eq!(blocks[0].text, "a -> b\n c=d");
+    assert!(blocks[0].args.is_empty());
+    assert_eq!(blocks[1].text, "key=value");
+    assert!(blocks[1].args.is_empty());
+}
@@ -0,0 +1,247 @@
+use ansi_term::Colour;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::diff;
+use crate::plugins;
+
+const DEFAULT_PLUGINS_DIR: &str = "./plugins";
+const INPUT_SUFFIX: &str = ".input.md";
+const OUTPUT_SUFFIX: &str = ".output.md";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FixtureStatus {
+    Passed,
+    Failed(String),
+    Error(String),
+}
+
+#[derive(Debug)]
+pub struct FixtureResult {
+    pub plugin: String,
+    pub fixture: String,
+    pub status: FixtureStatus,
+}
+
+fn get_plugin_template(name: &str) -> String {
+    format!(
+        r#"-- Transforms {{{{ #{name} ... }}}} blocks.
+--
+-- text    - a text of the block
+-- args    - a table of key=value arguments from the first line of the block
+-- context - a table with `chapter`, `path`, `renderer` and `last_change` of the chapter
+--
+-- Options from `plugins.{name}` in the config file are available as the `config` table.
+function transform(text, args, context)
+  local class = args.class or config.class or '{name}'
+
+  return '<div class="' .. class .. '">' .. text .. '</div>'
+end
+"#,
+        name = name
+    )
+}
+
+fn get_fixtures_dir(plugin_file: &str) -> PathBuf {
+    let path = Path::new(plugin_file);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{}.fixtures", stem))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/**
+ * @Article Plugins
+ *
+ * ## Developing plugins
+ *
+ * `fundoc plugin new <name>` creates `<name>.html.lua` in the plugins folder from a template,
+ * and an example fixture in `<name>.html.fixtures`. A fixture is a pair of files:
+ * `<fixture>.input.md` with a markdown text, and `<fixture>.output.md` with the expected result of
+ * the plugin.
+ *
+ * `fundoc plugin test` runs every plugin against its fixtures and shows a diff for every failed
+ * fixture, so a plugin can be developed without building the whole book. Trailing whitespaces at
+ * the end of files are ignored. The command exits with a non-zero code if any fixture fails.
+ */
+pub fn create_plugin(name: &str, plugins_dir: Option<&str>) -> Result<Vec<String>, String> {
+    if !is_valid_name(name) {
+        return Err(format!(
+            "\"{}\" isn't a valid plugin name, use only letters, digits, '-' and '_'",
+            name
+        ));
+    }
+
+    let plugins_dir = Path::new(plugins_dir.unwrap_or(DEFAULT_PLUGINS_DIR));
+    let plugin_file = plugins_dir.join(format!("{}.html.lua", name));
+
+    if plugin_file.exists() {
+        return Err(format!("{} already exists", plugin_file.display()));
+    }
+
+    let fixtures_dir = get_fixtures_dir(&plugin_file.to_string_lossy());
+    let input_file = fixtures_dir.join(format!("example{}", INPUT_SUFFIX));
+    let output_file = fixtures_dir.join(format!("example{}", OUTPUT_SUFFIX));
+    let files = [
+        (&plugin_file, get_plugin_template(name)),
+        (&input_file, format!("Text\n\n{{{{ #{} Hello }}}}\n", name)),
+        (
+            &output_file,
+            format!("Text\n\n<div class=\"{}\">Hello</div>\n", name),
+        ),
+    ];
+
+    fs::create_dir_all(&fixtures_dir).map_err(|e| e.to_string())?;
+
+    let mut created = vec![];
+    for (file, content) in files.iter() {
+        fs::write(file, content).map_err(|e| format!("Cannot write {}: {}", file.display(), e))?;
+        created.push(file.to_string_lossy().to_string());
+    }
+
+    Ok(created)
+}
+
+fn run_fixture(
+    plugins: &plugins::Plugins,
+    plugin: &plugins::Plugin,
+    input_file: &Path,
+    fixture: &str,
+) -> FixtureStatus {
+    let output_file = input_file.with_file_name(format!("{}{}", fixture, OUTPUT_SUFFIX));
+    let (input, expected) = match (
+        fs::read_to_string(input_file),
+        fs::read_to_string(&output_file),
+    ) {
+        (Ok(input), Ok(expected)) => (input, expected),
+        (Err(e), _) => return FixtureStatus::Error(format!("{}: {}", input_file.display(), e)),
+        (_, Err(e)) => return FixtureStatus::Error(format!("{}: {}", output_file.display(), e)),
+    };
+    let context = plugins::ChapterContext {
+        chapter: fixture.to_string(),
+        path: Some(input_file.to_string_lossy().to_string()),
+        renderer: plugin
+            .renderers
+            .first()
+            .cloned()
+            .unwrap_or_else(|| String::from("html")),
+        last_change: None,
+    };
+
+    match plugins.transform_text(plugin, &input, &context) {
+        Ok(actual) if actual.trim_end() == expected.trim_end() => FixtureStatus::Passed,
+        Ok(actual) => FixtureStatus::Failed(diff::format_diff(&diff::diff_lines(
+            expected.trim_end(),
+            actual.trim_end(),
+        ))),
+        Err(err) => FixtureStatus::Error(err.to_string()),
+    }
+}
+
+pub fn run_fixtures(plugins: &plugins::Plugins) -> Vec<FixtureResult> {
+    let mut results = vec![];
+
+    for plugin in plugins.sources() {
+        let Ok(entries) = fs::read_dir(get_fixtures_dir(&plugin.file)) else {
+            continue;
+        };
+        let mut inputs: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.to_string_lossy().ends_with(INPUT_SUFFIX))
+            .collect();
+        inputs.sort();
+
+        for input in inputs {
+            let file_name = input.file_name().unwrap().to_string_lossy().to_string();
+            let fixture = file_name.trim_end_matches(INPUT_SUFFIX).to_string();
+
+            results.push(FixtureResult {
+                plugin: plugin.file.clone(),
+                status: run_fixture(plugins, plugin, &input, &fixture),
+                fixture,
+            });
+        }
+    }
+
+    results
+}
+
+pub fn create_report(results: &[FixtureResult]) -> String {
+    if results.is_empty() {
+        return String::from("There are no plugin fixtures\n");
+    }
+
+    let mut report = String::from("");
+    let mut failed = 0;
+
+    for result in results {
+        let name = format!("{} {}", result.plugin, result.fixture);
+
+        report += &match &result.status {
+            FixtureStatus::Passed => format!("{} {}\n", Colour::Green.paint("ok"), name),
+            FixtureStatus::Failed(diff) => {
+                failed += 1;
+                format!("{} {}\n{}", Colour::Red.paint("failed"), name, diff)
+            }
+            FixtureStatus::Error(message) => {
+                failed += 1;
+                format!("{} {}\n{}\n", Colour::Red.paint("error"), name, message)
+            }
+        };
+    }
+
+    report + &format!("\n{} passed, {} failed\n", results.len() - failed, failed)
+}
+
+#[test]
+fn create_and_test_plugins() {
+    let dir = std::env::temp_dir().join("fundoc_plugin_dev");
+    fs::remove_dir_all(&dir).ok();
+    let plugins_dir = dir.to_string_lossy().to_string();
+
+    let files = create_plugin("note", Some(&plugins_dir)).unwrap();
+
+    assert_eq!(files.len(), 3);
+    assert!(create_plugin("note", Some(&plugins_dir)).is_err());
+    assert!(create_plugin("../note", Some(&plugins_dir)).is_err());
+
+    fs::write(
+        dir.join("note.html.fixtures/broken.input.md"),
+        "{{ #note Bye }}",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("note.html.fixtures/broken.output.md"),
+        "<div>Bye</div>",
+    )
+    .unwrap();
+
+    let mut config = crate::parser::get_test_config();
+    config.plugins_dir = Some(plugins_dir);
+    let plugins = plugins::Plugins::new(crate::lua_runtime::LuaRuntime::new(), config);
+    let results = run_fixtures(&plugins);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].fixture, "broken");
+    assert_eq!(
+        results[0].status,
+        FixtureStatus::Failed(String::from(
+            "```diff\n- <div>Bye</div>\n+ <div class=\"note\">Bye</div>\n```\n"
+        ))
+    );
+    assert_eq!(results[1].fixture, "example");
+    assert_eq!(results[1].status, FixtureStatus::Passed);
+    assert!(create_report(&results).ends_with("1 passed, 1 failed\n"));
+
+    fs::remove_dir_all(dir).ok();
+}
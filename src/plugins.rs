@@ -1,15 +1,407 @@
-use mdbook::book::{BookItem, Chapter};
+use mdbook::book::{Book, BookItem};
 use mdbook::preprocess::CmdPreprocessor;
-use regex::Regex;
-use serde_json::Error;
-use std::{env, fs, io, process};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{env, fmt, fs, io};
 
 use crate::config;
+use crate::diagrams;
+use crate::fs_utils;
+use crate::git;
+use crate::lua_fs;
 use crate::lua_runtime;
+use crate::native_plugin;
+use crate::parser::{self, KeywordHandler};
+use crate::plugin_blocks;
+use crate::wasm_plugin;
+
+#[derive(Debug)]
+pub enum PluginError {
+    UnclosedBlock {
+        plugin: String,
+        chapter: String,
+        line: usize,
+    },
+    Transform {
+        file: String,
+        chapter: String,
+        line: Option<usize>,
+        message: String,
+    },
+    Hook {
+        file: String,
+        hook: String,
+        line: Option<usize>,
+        message: String,
+    },
+    HookResult {
+        hook: String,
+        message: String,
+    },
+    SummaryPage {
+        file_name: String,
+    },
+    Book(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginError::UnclosedBlock {
+                plugin,
+                chapter,
+                line,
+            } => write!(
+                f,
+                "The \"{}\" block isn't closed in \"{}\" at line {}",
+                plugin, chapter, line
+            ),
+            PluginError::Transform {
+                file,
+                chapter,
+                line: Some(line),
+                message,
+            } => write!(f, "{}:{}: {} (in \"{}\")", file, line, message, chapter),
+            PluginError::Transform {
+                file,
+                chapter,
+                message,
+                ..
+            } => write!(f, "{}: {} (in \"{}\")", file, message, chapter),
+            PluginError::Hook {
+                file,
+                hook,
+                line: Some(line),
+                message,
+            } => write!(f, "{}:{}: {} (in {})", file, line, message, hook),
+            PluginError::Hook {
+                file,
+                hook,
+                message,
+                ..
+            } => write!(f, "{}: {} (in {})", file, message, hook),
+            PluginError::HookResult { hook, message } => {
+                write!(f, "Invalid result of {}: {}", hook, message)
+            }
+            PluginError::SummaryPage { file_name } => write!(
+                f,
+                "The \"{}\" page from on_summary conflicts with another document",
+                file_name
+            ),
+            PluginError::Book(err) => write!(f, "Cannot read the book from mdBook: {}", err),
+            PluginError::Json(err) => write!(f, "Cannot process the book: {}", err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PluginError {
+    fn from(err: serde_json::Error) -> Self {
+        PluginError::Json(err)
+    }
+}
+
+const DEFAULT_INSTRUCTION_LIMIT: u64 = 100_000_000;
+const DEFAULT_MEMORY_LIMIT_MB: usize = 64;
+
+enum PluginKind {
+    Lua {
+        source: String,
+        environment: RefCell<Option<rlua::RegistryKey>>,
+    },
+    Native(native_plugin::NativePlugin),
+    Wasm(Box<wasm_plugin::WasmPlugin>),
+    Diagram {
+        diagram: diagrams::Diagram,
+        cache: diagrams::DiagramCache,
+    },
+}
+
+pub struct Plugin {
+    pub name: String,
+    pub file: String,
+    pub renderers: Vec<String>,
+    kind: PluginKind,
+}
+
+impl Plugin {
+    pub fn supports(&self, renderer: &str) -> bool {
+        self.renderers.is_empty() || self.renderers.iter().any(|name| name == renderer)
+    }
+}
+
+/**
+ * @Article Plugins
+ *
+ * The third argument of `transform` is a context table with the following fields:
+ *
+ * - `chapter` - a title of the chapter which contains the block
+ * - `path` - a path to the chapter file
+ * - `renderer` - a name of the output format (`html` for mdBook, `markdown` for plain markdown)
+ * - `last_change` - a table with `sha`, `author` and `date` of the last change of the article if
+ *   `git_metadata` is enabled
+ */
+pub struct ChapterContext {
+    pub chapter: String,
+    pub path: Option<String>,
+    pub renderer: String,
+    pub last_change: Option<git::Commit>,
+}
+
+impl ChapterContext {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "chapter": self.chapter,
+            "path": self.path,
+            "renderer": self.renderer,
+            "last_change": self.last_change.as_ref().map(|commit| serde_json::json!({
+                "sha": commit.sha,
+                "author": commit.author,
+                "date": commit.date,
+            })),
+        })
+    }
+}
+
+fn article_to_json(article: &parser::Article) -> serde_json::Value {
+    serde_json::json!({
+        "topic": article.topic,
+        "content": article.content,
+        "path": article.path,
+        "start_line": article.start_line,
+        "metadata": article.metadata,
+    })
+}
 
 pub struct Plugins {
     lua_runtime: lua_runtime::LuaRuntime,
-    config: config::Config,
+    sources: Vec<Plugin>,
+    options: HashMap<String, serde_json::Value>,
+}
+
+fn get_plugin_name(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+
+    if extension != "lua" && extension != "wasm" && extension != env::consts::DLL_EXTENSION {
+        return None;
+    }
+
+    file_name.split('.').next().map(String::from)
+}
+
+fn get_plugin_renderers(path: &Path) -> Vec<String> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let parts: Vec<&str> = file_name.split('.').collect();
+
+    match parts.len() {
+        0..=2 => vec![],
+        len => parts[1..len - 1]
+            .iter()
+            .map(|part| part.to_string())
+            .collect(),
+    }
+}
+
+fn get_instruction_limit(config: &config::Config) -> u64 {
+    config
+        .plugins_instruction_limit
+        .unwrap_or(DEFAULT_INSTRUCTION_LIMIT)
+}
+
+fn get_memory_limit(config: &config::Config) -> usize {
+    config
+        .plugins_memory_limit_mb
+        .unwrap_or(DEFAULT_MEMORY_LIMIT_MB)
+        * 1024
+        * 1024
+}
+
+fn read_plugin(path: &Path, config: &config::Config) -> Option<Plugin> {
+    let name = get_plugin_name(path)?;
+    let file = path.to_string_lossy().to_string();
+    let kind = match path.extension()?.to_str()? {
+        "lua" => PluginKind::Lua {
+            source: fs::read_to_string(path).ok()?,
+            environment: RefCell::new(None),
+        },
+        "wasm" => PluginKind::Wasm(Box::new(wasm_plugin::WasmPlugin::load(
+            &file,
+            get_instruction_limit(config),
+            get_memory_limit(config),
+        ))),
+        "so" | "dylib" | "dll" if config.native_plugins.unwrap_or(false) => {
+            PluginKind::Native(native_plugin::NativePlugin::load(&file))
+        }
+        "so" | "dylib" | "dll" => {
+            eprintln!(
+                "{} is skipped: set `native_plugins` to true in the config file to load native plugins",
+                file
+            );
+            return None;
+        }
+        _ => return None,
+    };
+
+    Some(Plugin {
+        name,
+        file,
+        renderers: get_plugin_renderers(path),
+        kind,
+    })
+}
+
+fn read_plugin_sources(config: &config::Config) -> Vec<Plugin> {
+    let mut paths: Vec<_> = match config.plugins_dir.as_ref().map(fs::read_dir) {
+        Some(Ok(entries)) => entries.flatten().map(|entry| entry.path()).collect(),
+        _ => vec![],
+    };
+    paths.sort();
+
+    let mut plugins: Vec<Plugin> = paths
+        .iter()
+        .filter_map(|path| read_plugin(path, config))
+        .collect();
+    let cache = diagrams::DiagramCache::new(config);
+
+    for diagram in diagrams::DIAGRAMS.iter() {
+        if plugins.iter().all(|plugin| plugin.name != diagram.name()) {
+            plugins.push(Plugin {
+                name: diagram.name().to_string(),
+                file: format!("{} (built-in)", diagram.name()),
+                renderers: vec![],
+                kind: PluginKind::Diagram {
+                    diagram: *diagram,
+                    cache: cache.clone(),
+                },
+            });
+        }
+    }
+
+    plugins
+}
+
+/**
+ * @Article Plugins
+ *
+ * Options of a plugin can be set in the `plugins` field of `fundoc.json` or in a table of the
+ * preprocessor in `book.toml`. Options from `book.toml` override options from `fundoc.json`:
+ *
+ * ```toml
+ * [preprocessor.fundoc.mermaid]
+ * theme = "dark"
+ * ```
+ */
+fn merge_options(
+    options: &mut HashMap<String, serde_json::Value>,
+    book_options: &serde_json::Value,
+) {
+    let Some(book_options) = book_options.as_object() else {
+        return;
+    };
+
+    for (name, book_plugin_options) in book_options {
+        let Some(book_plugin_options) = book_plugin_options.as_object() else {
+            continue;
+        };
+
+        let plugin_options = options
+            .entry(name.clone())
+            .or_insert_with(|| serde_json::json!({}));
+
+        match plugin_options.as_object_mut() {
+            Some(plugin_options) => plugin_options.extend(book_plugin_options.clone()),
+            None => *plugin_options = serde_json::Value::Object(book_plugin_options.clone()),
+        }
+    }
+}
+
+const DEFAULT_PREPROCESSOR_NAME: &str = "fundoc";
+const BOOK_CONFIG_FILE: &str = "book.toml";
+
+fn is_fundoc_command(command: &str) -> bool {
+    let words: Vec<&str> = command.split_whitespace().collect();
+
+    words
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|program| program == "fundoc")
+        && words
+            .iter()
+            .any(|word| *word == "-e" || *word == "--extension")
+}
+
+fn find_preprocessor_name(
+    preprocessors: &serde_json::Map<String, serde_json::Value>,
+    name: Option<&str>,
+) -> Option<String> {
+    if let Some(name) = name {
+        return Some(name.to_string());
+    }
+
+    let names: Vec<&String> = preprocessors
+        .iter()
+        .filter(|(_, preprocessor)| {
+            preprocessor["command"]
+                .as_str()
+                .is_some_and(is_fundoc_command)
+        })
+        .map(|(name, _)| name)
+        .collect();
+
+    match names.as_slice() {
+        [name] => Some(name.to_string()),
+        names => {
+            if names.len() > 1 {
+                eprintln!(
+                    "Several preprocessors run Fundoc, add `--name <preprocessor>` to their commands to use their options"
+                );
+            }
+
+            preprocessors
+                .contains_key(DEFAULT_PREPROCESSOR_NAME)
+                .then(|| String::from(DEFAULT_PREPROCESSOR_NAME))
+        }
+    }
+}
+
+/**
+ * @Article Plugins
+ *
+ * Several preprocessors can run Fundoc with different options and plugins. Pass a name of the
+ * preprocessor with `--name`, so Fundoc can find its table in `book.toml`. The `plugins` field
+ * limits plugins which the preprocessor runs:
+ *
+ * ```toml
+ * [preprocessor.diagrams]
+ * command = "fundoc -e --name diagrams"
+ * plugins = ["mermaid"]
+ * mermaid = { theme = "dark" }
+ *
+ * [preprocessor.links]
+ * command = "fundoc -e --name links"
+ * plugins = ["jira"]
+ * after = ["diagrams"]
+ * ```
+ *
+ * `--name` can be omitted if only one preprocessor runs Fundoc.
+ */
+fn read_book_options(book_config: &mdbook::Config, name: Option<&str>) -> serde_json::Value {
+    let preprocessors = book_config
+        .get("preprocessor")
+        .and_then(|preprocessors| serde_json::to_value(preprocessors).ok())
+        .unwrap_or_default();
+    let Some(preprocessors) = preprocessors.as_object() else {
+        return serde_json::Value::Null;
+    };
+
+    find_preprocessor_name(preprocessors, name)
+        .and_then(|name| preprocessors.get(&name).cloned())
+        .unwrap_or_default()
 }
 
 /**
@@ -28,122 +420,942 @@ pub struct Plugins {
  * {{ #your-plugin-name
  *  any text here
  * }}
- * 
+ *
  * ```
  *
  * To create a plugin for parsing these blocks, you should add a file called `your-plugin-name.html.lua` into the plugins folder. By default, it's `./plugins`, but it's possible to change it in the config file.
  * Then you should add the following strings into the `book.toml` file:
  *
  * ```toml
- * [preprocessor.your-plugin-name]
+ * [preprocessor.fundoc]
  * command = "fundoc -e"
  * ```
- * The `-e` artument runs Fundoc in mdBook extension mode.
+ * The `-e` artument runs Fundoc in mdBook extension mode. One preprocessor runs all plugins from
+ * the plugins folder, so you don't need to add a preprocessor for each plugin.
  *
  * And in your your-plugin-name.html.lua, you should implement text transformation like this:
  *
  * ```lua
- * function transform(text)
- *   result = 'transformted text' 
+ * function transform(text, args, context)
+ *   return 'transformted text'
  * end
  * ```
  *
- * Fundoc calls the `transform` function and passes the parsed text from the marked text block. You can transform it in any way you want.
+ * Fundoc calls the `transform` function and passes the parsed text from the marked text block,
+ * a table of the block arguments and a context of the chapter. You can transform it in any way you
+ * want and return the result. Setting the global `result` variable works too. Options of the plugin
+ * are available in the global `config` table.
+ *
+ * If a plugin fails, Fundoc stops the build and reports the plugin file, the line of the Lua code
+ * and the chapter.
  *
  */
 impl Plugins {
     pub fn new(lua_runtime: lua_runtime::LuaRuntime, config: config::Config) -> Self {
+        let sources = read_plugin_sources(&config);
+        let root = env::current_dir().unwrap_or_default();
+
+        if let Err(err) = lua_runtime.set_fs(lua_fs::FsSandbox::new(&root, &config)) {
+            eprintln!("{}", err);
+        }
+
+        lua_runtime.set_limits(
+            Some(get_instruction_limit(&config)),
+            Some(get_memory_limit(&config)),
+        );
+
+        let options = config.plugins.unwrap_or_default();
+
         Self {
             lua_runtime,
-            config,
+            sources,
+            options,
         }
     }
 
-    pub fn run_as_plugin(&self) -> Result<(), Error> {
-        if self.config.plugins_dir.is_none() {
-            panic!("There's no a plugin directory");
+    /**
+     * @Article Plugins
+     *
+     * Plugins are applied to generated documents as well, so they work without mdBook too. Every
+     * `*.lua` file, native library and `*.wasm` module from the plugins folder transforms blocks with a name of the
+     * file (without extensions) before documents are written.
+     */
+    pub fn transform(
+        &self,
+        content: &str,
+        context: &ChapterContext,
+    ) -> Result<String, PluginError> {
+        let mut content = content.to_string();
+
+        for plugin in &self.sources {
+            if plugin.supports(&context.renderer) {
+                content = self.transform_text(plugin, &content, context)?;
+            }
         }
 
-        let paths = fs::read_dir(self.config.plugins_dir.as_ref().unwrap());
-        let args: Vec<String> = env::args().collect();
+        Ok(content)
+    }
+
+    pub fn sources(&self) -> &[Plugin] {
+        &self.sources
+    }
+
+    pub fn transform_text(
+        &self,
+        plugin: &Plugin,
+        content: &str,
+        context: &ChapterContext,
+    ) -> Result<String, PluginError> {
+        let blocks = plugin_blocks::find_blocks(content, &plugin.name).map_err(|err| {
+            PluginError::UnclosedBlock {
+                plugin: plugin.name.clone(),
+                chapter: context.chapter.clone(),
+                line: err.line,
+            }
+        })?;
+        let options = self.options.get(&plugin.name).cloned().unwrap_or_default();
+        let context_json = context.to_json();
+        let mut result = String::from("");
+        let mut position = 0;
 
-        /**
-         * @Article Plugins
-         * > [note] mdBook runs Fundoc twice. The first time fundoc should exit with 0 code. The
-         * second time Fundoc transforms the text.
-         */
-        if args.len() > 3 {
-            process::exit(0x0100);
+        for block in blocks {
+            result += &content[position..block.start];
+            position = block.end;
+            result += &self
+                .parse_chapter(plugin, block, &options, &context_json)
+                .map_err(|(line, message)| PluginError::Transform {
+                    file: plugin.file.clone(),
+                    chapter: context.chapter.clone(),
+                    line,
+                    message,
+                })?;
         }
 
-        let (ctx, mut book) = CmdPreprocessor::parse_input(io::stdin()).unwrap();
-        let preprocessor = self.parse_private_preprocessor_value(format!("{:?}", ctx));
+        Ok(result + &content[position..])
+    }
+
+    fn transform_book(&self, book: &mut Book, renderer: &str) -> Result<(), PluginError> {
+        let mut result = Ok(());
 
-        for file in paths.unwrap().flatten() {
-            let file_path = file.path();
-            let (Some(preprocessor_value), Some(path_str)) = (&preprocessor, file_path.to_str()) else {
-                serde_json::to_writer(io::stdout(), &book)?;
-                break;
-            };
+        book.for_each_mut(|item| {
+            if let (BookItem::Chapter(chapter), Ok(_)) = (item, &result) {
+                let context = ChapterContext {
+                    chapter: chapter.name.clone(),
+                    path: chapter
+                        .path
+                        .as_ref()
+                        .map(|path| fs_utils::to_slash_path(path)),
+                    renderer: renderer.to_string(),
+                    last_change: None,
+                };
+
+                match self.transform(&chapter.content, &context) {
+                    Ok(content) => chapter.content = content,
+                    Err(err) => result = Err(err),
+                }
+            }
+        });
+
+        result
+    }
+
+    /**
+     * @Article Plugins
+     *
+     * ## Renderers
+     *
+     * A plugin declares renderers it supports in its file name: `mermaid.html.lua` works only for
+     * the `html` renderer, `diagram.html.pdf.lua` works for `html` and `pdf`, and `bold.lua` works
+     * for every renderer. Plugins with the same name can implement different transforms for
+     * different renderers, for example `mermaid.html.lua` and `mermaid.markdown.lua`. Documents
+     * generated without mdBook use the `markdown` renderer. With `mdbook` enabled Fundoc keeps
+     * blocks in generated documents and transforms them when mdBook runs it as a preprocessor, so
+     * every renderer gets its own result.
+     *
+     * > [note] Before running Fundoc as a preprocessor mdBook asks if it supports a renderer with
+     * > `fundoc -e supports <renderer>`. Fundoc exits with 0 code if at least one plugin supports
+     * > the renderer, otherwise mdBook doesn't run the preprocessor for this renderer.
+     */
+    pub fn supports_renderer(&self, renderer: &str) -> bool {
+        self.sources.iter().any(|plugin| plugin.supports(renderer))
+    }
+
+    pub fn apply_book_config(&mut self, book_config: &mdbook::Config, name: Option<&str>) {
+        let book_options = read_book_options(book_config, name);
+
+        if let Some(plugins) = book_options["plugins"].as_array() {
+            let plugins: Vec<&str> = plugins.iter().filter_map(|name| name.as_str()).collect();
+
+            self.sources
+                .retain(|plugin| plugins.contains(&plugin.name.as_str()));
+        }
+
+        merge_options(&mut self.options, &book_options);
+    }
+
+    // mdBook doesn't pass the book config when it checks renderers, so it's read from the disk
+    pub fn read_book_config(&mut self, name: Option<&str>) {
+        if let Ok(book_config) = mdbook::Config::from_disk(BOOK_CONFIG_FILE) {
+            self.apply_book_config(&book_config, name);
+        }
+    }
+
+    pub fn run_as_plugin(&mut self, name: Option<&str>) -> Result<(), PluginError> {
+        self.process_book(io::stdin(), io::stdout(), name)
+    }
 
-            if !path_str.contains(preprocessor_value) {
-                serde_json::to_writer(io::stdout(), &book)?;
-                break;
+    fn process_book(
+        &mut self,
+        input: impl io::Read,
+        output: impl io::Write,
+        name: Option<&str>,
+    ) -> Result<(), PluginError> {
+        let (ctx, mut book) =
+            CmdPreprocessor::parse_input(input).map_err(|e| PluginError::Book(e.to_string()))?;
+
+        self.apply_book_config(&ctx.config, name);
+
+        self.transform_book(&mut book, &ctx.renderer)?;
+
+        Ok(serde_json::to_writer(output, &book)?)
+    }
+
+    /**
+     * @Article Plugins
+     *
+     * ## Hooks
+     *
+     * Lua plugins can define hooks which are called at each stage of documentation generation:
+     *
+     * - `on_article(article)` - is called for every parsed article, the article is a table with
+     *   `topic`, `content`, `path`, `start_line` and `metadata` fields
+     * - `on_document(doc)` - is called for every document after articles are merged into documents,
+     *   the document is a table with `title`, `file_name` and `content` fields
+     * - `on_summary(summary)` - is called with a list of summary items (`title` and `file_name`)
+     *   before the summary is written
+     * - `on_finish(files)` - is called with a list of written files
+     *
+     * A hook can return nothing to keep a value, `false` to drop it (for example, to filter
+     * articles), or a changed table. `on_summary` can reorder items, and an item with `content`
+     * creates an extra page (an index, a glossary, etc.):
+     *
+     * ```lua
+     * function on_summary(summary)
+     *   table.insert(summary, 1, { title = 'Glossary', file_name = 'glossary.md', content = '...' })
+     *   return summary
+     * end
+     * ```
+     *
+     * If several plugins define the same hook, they are called in the alphabetical order of their
+     * files, and each one gets the result of the previous one. For versioned documentation
+     * `on_summary` is called once per version. `on_summary` must return a list of items with `title`
+     * and `file_name`, and a page from it can't replace a generated document or another page:
+     * otherwise the generation stops with an error.
+     */
+    pub fn run_hook(
+        &self,
+        hook: &str,
+        value: serde_json::Value,
+    ) -> Result<Option<serde_json::Value>, PluginError> {
+        let mut value = value;
+
+        for plugin in &self.sources {
+            let hook_error = |err: rlua::Error| {
+                let (line, message) = lua_runtime::describe_error(&err);
+
+                PluginError::Hook {
+                    file: plugin.file.clone(),
+                    hook: hook.to_string(),
+                    line,
+                    message,
+                }
+            };
+            let Some(environment) = self.get_lua_environment(plugin).map_err(hook_error)? else {
+                continue;
             };
-            let regex_str = String::from(r"\{\{ #") + preprocessor_value + r"[\w\W]*\}\}";
-            let re = Regex::new(&regex_str).unwrap();
 
-            let plugin_src = fs::read_to_string(file_path).unwrap();
-            book.sections = book
-                .sections
+            let result = self
+                .lua_runtime
+                .call_hook(environment.as_ref().unwrap(), hook, &value)
+                .map_err(hook_error)?;
+
+            match result {
+                Some(serde_json::Value::Bool(false)) => return Ok(None),
+                Some(result) => value = result,
+                None => {}
+            }
+        }
+
+        Ok(Some(value))
+    }
+
+    pub fn on_article(
+        &self,
+        article: parser::Article,
+    ) -> Result<Option<parser::Article>, PluginError> {
+        let Some(result) = self.run_hook("on_article", article_to_json(&article))? else {
+            return Ok(None);
+        };
+        let get_string =
+            |field: &str, default: &str| result[field].as_str().unwrap_or(default).to_string();
+        let metadata = match result["metadata"].as_object() {
+            Some(metadata) => metadata
                 .iter()
-                .map(|section| match section {
-                    BookItem::Chapter(chapter) => {
-                        let mut content = chapter.content.clone();
-
-                        #[allow(clippy::redundant_clone)]
-                        for capture in re.captures_iter(&content.clone()) {
-                            let src_text = capture.get(0).map_or("", |c| c.as_str()).to_string();
-
-                            let parsed_fragment = self.parse_chapter(
-                                preprocessor_value.to_string(),
-                                plugin_src.clone(),
-                                src_text.clone(),
-                            );
-                            content = content.replace(&src_text, &parsed_fragment);
-                        }
-
-                        BookItem::Chapter(Chapter {
-                            content,
-                            ..chapter.clone()
-                        })
-                    }
-                    _ => section.clone(),
-                })
-                .collect();
-
-            serde_json::to_writer(io::stdout(), &book)?;
+                .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                .collect(),
+            None => article.metadata.clone(),
+        };
+
+        Ok(Some(parser::Article {
+            topic: get_string("topic", &article.topic),
+            content: get_string("content", &article.content),
+            metadata,
+            ..article
+        }))
+    }
+
+    // returns None for plugins which aren't written in Lua
+    fn get_lua_environment<'p>(
+        &self,
+        plugin: &'p Plugin,
+    ) -> rlua::Result<Option<std::cell::Ref<'p, Option<rlua::RegistryKey>>>> {
+        let PluginKind::Lua {
+            source,
+            environment,
+        } = &plugin.kind
+        else {
+            return Ok(None);
+        };
+
+        self.load_lua_plugin(&plugin.file, source, environment)?;
+
+        Ok(Some(environment.borrow()))
+    }
+
+    fn load_lua_plugin(
+        &self,
+        file: &str,
+        source: &str,
+        environment: &RefCell<Option<rlua::RegistryKey>>,
+    ) -> rlua::Result<()> {
+        if environment.borrow().is_none() {
+            *environment.borrow_mut() = Some(self.lua_runtime.load(file, source)?);
         }
 
         Ok(())
     }
 
-    fn parse_private_preprocessor_value(&self, stringified_ctx: String) -> Option<String> {
-        let re = Regex::new(r#""preprocessor": Table\(\{"*(.*?) *":"#).unwrap();
+    fn parse_chapter(
+        &self,
+        plugin: &Plugin,
+        block: plugin_blocks::Block,
+        options: &serde_json::Value,
+        context: &serde_json::Value,
+    ) -> Result<String, (Option<usize>, String)> {
+        match &plugin.kind {
+            PluginKind::Lua {
+                source,
+                environment,
+            } => {
+                self.load_lua_plugin(&plugin.file, source, environment)
+                    .map_err(|err| lua_runtime::describe_error(&err))?;
+                let environment = environment.borrow();
 
-        match re.captures(&stringified_ctx) {
-            Some(captures) => captures.get(1).map(|c| String::from(c.as_str())),
-            _ => None,
+                self.lua_runtime
+                    .call_transform(
+                        environment.as_ref().unwrap(),
+                        block.text,
+                        &block.args,
+                        options,
+                        context,
+                    )
+                    .map_err(|err| lua_runtime::describe_error(&err))
+            }
+            PluginKind::Native(native_plugin) => native_plugin
+                .transform(&block.text, &block.args, options, context)
+                .map_err(|message| (None, message)),
+            PluginKind::Wasm(wasm_plugin) => wasm_plugin
+                .transform(&block.text)
+                .map_err(|message| (None, message)),
+            PluginKind::Diagram { diagram, cache } => diagram
+                .render(
+                    &block.text,
+                    &block.args,
+                    options,
+                    context["renderer"].as_str().unwrap_or_default(),
+                    cache,
+                )
+                .map_err(|message| (None, message)),
         }
     }
+}
+
+/**
+ * @Article Plugins
+ *
+ * ## Custom keywords
+ *
+ * Lua plugins can add their own keywords to the syntax of documentation comments. Handlers of
+ * keywords are functions in the global `keywords` table of a plugin:
+ *
+ * ```lua
+ * keywords = {
+ *   Jira = function(args, article)
+ *     return '[' .. args .. '](https://jira.company.com/browse/' .. args .. ')'
+ *   end,
+ *   Endpoint = function(args, article)
+ *     return { content = '`' .. args .. '`', metadata = { endpoint = args } }
+ *   end,
+ * }
+ * ```
+ *
+ * After that `@Jira ABC-123` in an article is replaced by a link to the issue. A handler receives
+ * the text after the keyword and a table of the current article (`topic`, `content`, `path`,
+ * `start_line` and `metadata`). It can return a string which is appended to the article, or a
+ * table with `content` and `metadata` fields. Metadata is a table of strings which is added to the
+ * metadata of the article. Keywords of Fundoc can't be overridden.
+ */
+fn describe_keyword_error(plugin: &Plugin, err: rlua::Error) -> String {
+    match lua_runtime::describe_error(&err) {
+        (Some(line), message) => format!("{}:{}: {}", plugin.file, line, message),
+        (None, message) => format!("{}: {}", plugin.file, message),
+    }
+}
+
+impl KeywordHandler for Plugins {
+    fn keywords(&self) -> Result<Vec<String>, String> {
+        let mut keywords: Vec<String> = vec![];
+
+        for plugin in &self.sources {
+            let keyword_error = |err| describe_keyword_error(plugin, err);
+            let Some(environment) = self.get_lua_environment(plugin).map_err(keyword_error)? else {
+                continue;
+            };
+            let names = self
+                .lua_runtime
+                .keyword_names(environment.as_ref().unwrap())
+                .map_err(keyword_error)?;
+
+            for name in names {
+                if !keywords.contains(&name) {
+                    keywords.push(name);
+                }
+            }
+        }
+
+        Ok(keywords)
+    }
 
-    fn parse_chapter(&self, preprocessor: String, lua_src: String, src_text: String) -> String {
-        self.lua_runtime.exec(lua_src);
+    fn handle_keyword(
+        &self,
+        keyword: &str,
+        args: &str,
+        article: &mut parser::Article,
+    ) -> Result<(), String> {
+        let article_json = article_to_json(article);
 
-        let preprocessor_header = String::from("{{ #") + &preprocessor;
-        let mut extracted_text = src_text.replace(&preprocessor_header, "");
-        extracted_text = extracted_text.replace("}}", "");
+        for plugin in &self.sources {
+            let keyword_error = |err| describe_keyword_error(plugin, err);
+            let Some(environment) = self.get_lua_environment(plugin).map_err(keyword_error)? else {
+                continue;
+            };
+            let environment = environment.as_ref().unwrap();
+
+            if !self
+                .lua_runtime
+                .keyword_names(environment)
+                .map_err(keyword_error)?
+                .iter()
+                .any(|name| name == keyword)
+            {
+                continue;
+            }
+
+            let (content, metadata) = self
+                .lua_runtime
+                .call_keyword(environment, keyword, args, &article_json)
+                .map_err(keyword_error)?;
+
+            if let Some(content) = content {
+                article.content += &format!("{}\n", content);
+            }
+            article.metadata.extend(metadata);
+
+            return Ok(());
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn get_plugin_name_from_path() {
+    assert_eq!(
+        get_plugin_name(Path::new("./plugins/mermaid.html.lua")),
+        Some(String::from("mermaid"))
+    );
+    assert_eq!(
+        get_plugin_name(&Path::new("./plugins/bold").with_extension(env::consts::DLL_EXTENSION)),
+        Some(String::from("bold"))
+    );
+    assert_eq!(get_plugin_name(Path::new("./plugins/README.md")), None);
+}
+
+#[test]
+fn get_plugin_renderers_from_path() {
+    assert_eq!(
+        get_plugin_renderers(Path::new("./plugins/mermaid.html.lua")),
+        vec!["html"]
+    );
+    assert_eq!(
+        get_plugin_renderers(Path::new("./plugins/diagram.html.pdf.lua")),
+        vec!["html", "pdf"]
+    );
+    assert!(get_plugin_renderers(Path::new("./plugins/bold.lua")).is_empty());
+}
+
+#[test]
+fn load_native_plugins_only_if_enabled() {
+    let dir = std::env::temp_dir().join("fundoc_plugins_native");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("bold.so"), "").unwrap();
+
+    let mut config = crate::parser::get_test_config();
+    config.plugins_dir = Some(dir.to_string_lossy().to_string());
+    let has_native_plugin = |config: &config::Config| {
+        read_plugin_sources(config)
+            .iter()
+            .any(|plugin| plugin.name == "bold")
+    };
+
+    assert!(!has_native_plugin(&config));
+
+    config.native_plugins = Some(true);
+    assert!(has_native_plugin(&config));
+
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn transform_blocks_with_plugins_for_renderer() {
+    let mut markdown_plugin =
+        get_test_plugin("function transform(text) return '**' .. text .. '**' end");
+    markdown_plugin.file = String::from("plugins/bold.markdown.lua");
+    markdown_plugin.renderers = vec![String::from("markdown")];
+
+    let plugins = Plugins {
+        lua_runtime: lua_runtime::LuaRuntime::new(),
+        sources: vec![
+            markdown_plugin,
+            get_test_plugin("function transform(text) return '<b>' .. text .. '</b>' end"),
+        ],
+        options: HashMap::new(),
+    };
+    let mut context = get_test_context();
+
+    assert_eq!(
+        plugins.transform("{{ #bold a }}", &context).unwrap(),
+        "<b>a</b>"
+    );
+
+    context.renderer = String::from("markdown");
+    assert_eq!(
+        plugins.transform("{{ #bold a }}", &context).unwrap(),
+        "**a**"
+    );
+    assert!(plugins.supports_renderer("html"));
+    assert!(!plugins.supports_renderer("pdf"));
+}
+
+#[cfg(test)]
+fn get_test_plugin(source: &str) -> Plugin {
+    Plugin {
+        name: String::from("bold"),
+        file: String::from("plugins/bold.html.lua"),
+        renderers: vec![String::from("html")],
+        kind: PluginKind::Lua {
+            source: source.to_string(),
+            environment: RefCell::new(None),
+        },
+    }
+}
+
+#[cfg(test)]
+pub fn get_test_plugins(source: &str) -> Plugins {
+    Plugins {
+        lua_runtime: lua_runtime::LuaRuntime::new(),
+        sources: vec![get_test_plugin(source)],
+        options: HashMap::new(),
+    }
+}
 
-        self.lua_runtime.call_transform(extracted_text).unwrap()
+#[cfg(test)]
+fn get_test_context() -> ChapterContext {
+    ChapterContext {
+        chapter: String::from("Chapter"),
+        path: Some(String::from("chapter.md")),
+        renderer: String::from("html"),
+        last_change: None,
     }
 }
+
+#[test]
+fn transform_plugin_blocks() {
+    let plugins = Plugins::new(
+        lua_runtime::LuaRuntime::new(),
+        crate::parser::get_test_config(),
+    );
+    let plugin = get_test_plugin("function transform(text) result = '<b>' .. text .. '</b>' end");
+
+    let result = plugins
+        .transform_text(
+            &plugin,
+            "{{ #bold a }} text {{ #bold b }}",
+            &get_test_context(),
+        )
+        .unwrap();
+
+    assert_eq!(result, "<b>a</b> text <b>b</b>");
+}
+
+#[test]
+fn transform_nested_chapters() {
+    let plugins = get_test_plugins("function transform(text) result = '<b>' .. text .. '</b>' end");
+    let mut nested_chapter = mdbook::book::Chapter::new(
+        "Nested",
+        String::from("{{ #bold nested }}"),
+        "nested.md",
+        vec![],
+    );
+    nested_chapter.sub_items = vec![BookItem::Chapter(mdbook::book::Chapter::new(
+        "Deep",
+        String::from("{{ #bold deep }}"),
+        "deep.md",
+        vec![],
+    ))];
+    let mut book = Book::new();
+    book.push_item(nested_chapter);
+
+    plugins.transform_book(&mut book, "html").unwrap();
+
+    let contents: Vec<String> = book
+        .iter()
+        .filter_map(|item| match item {
+            BookItem::Chapter(chapter) => Some(chapter.content.clone()),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(contents, vec!["<b>nested</b>", "<b>deep</b>"]);
+}
+
+#[test]
+fn report_unclosed_plugin_blocks() {
+    let plugins = Plugins::new(
+        lua_runtime::LuaRuntime::new(),
+        crate::parser::get_test_config(),
+    );
+
+    let result = plugins.transform_text(
+        &get_test_plugin(""),
+        "text\n{{ #bold test",
+        &get_test_context(),
+    );
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "The \"bold\" block isn't closed in \"Chapter\" at line 2"
+    );
+}
+
+#[test]
+fn merge_plugin_options() {
+    let mut options: HashMap<String, serde_json::Value> = HashMap::new();
+    options.insert(
+        String::from("mermaid"),
+        serde_json::json!({ "theme": "default", "width": 100 }),
+    );
+    let book_options = serde_json::json!({
+        "command": "fundoc -e",
+        "mermaid": { "theme": "dark" },
+        "chart": { "color": "red" },
+    });
+
+    merge_options(&mut options, &book_options);
+
+    assert_eq!(
+        options["mermaid"],
+        serde_json::json!({ "theme": "dark", "width": 100 })
+    );
+    assert_eq!(options["chart"], serde_json::json!({ "color": "red" }));
+    assert_eq!(options.get("command"), None);
+}
+
+#[test]
+fn read_options_of_several_preprocessors() {
+    let book_config: mdbook::Config = r#"
+        [preprocessor.diagrams]
+        command = "fundoc -e --name diagrams"
+        plugins = ["mermaid"]
+        mermaid = { theme = "dark" }
+
+        [preprocessor.links]
+        command = "./bin/fundoc --extension --name links"
+        jira = { host = "https://jira" }
+
+        [preprocessor.other]
+        command = "mdbook-other -e"
+        "#
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        read_book_options(&book_config, Some("links"))["jira"],
+        serde_json::json!({ "host": "https://jira" })
+    );
+    assert_eq!(
+        read_book_options(&book_config, None),
+        serde_json::Value::Null
+    );
+
+    let single_config: mdbook::Config = r#"
+        [preprocessor.docs]
+        command = "fundoc -e"
+        mermaid = { theme = "forest" }
+        "#
+    .parse()
+    .unwrap();
+
+    assert_eq!(
+        read_book_options(&single_config, None)["mermaid"],
+        serde_json::json!({ "theme": "forest" })
+    );
+
+    let mut plugins = get_test_plugins("function transform(text) return text end");
+    plugins.apply_book_config(&book_config, Some("diagrams"));
+
+    assert!(plugins.sources.is_empty());
+    assert_eq!(
+        plugins.options["mermaid"],
+        serde_json::json!({ "theme": "dark" })
+    );
+}
+
+#[test]
+fn transform_plugin_blocks_with_args_and_options() {
+    let mut config = crate::parser::get_test_config();
+    let mut options = HashMap::new();
+    options.insert(String::from("bold"), serde_json::json!({ "tag": "b" }));
+    config.plugins = Some(options);
+    let plugins = Plugins::new(lua_runtime::LuaRuntime::new(), config);
+    let plugin = get_test_plugin(
+        "function transform(text, args)
+          result = '<' .. config.tag .. ' class=\"' .. (args.class or '') .. '\">' .. text .. '</' .. config.tag .. '>'
+        end",
+    );
+
+    let result = plugins
+        .transform_text(
+            &plugin,
+            "{{ #bold class=\"x y\"\ntext\n}}",
+            &get_test_context(),
+        )
+        .unwrap();
+
+    assert_eq!(result, "<b class=\"x y\">text</b>");
+}
+
+#[test]
+fn allow_only_known_mermaid_themes() {
+    let plugins = Plugins::new(
+        lua_runtime::LuaRuntime::new(),
+        crate::parser::get_test_config(),
+    );
+    let plugin = get_test_plugin(include_str!("../plugins/mermaid.html.lua"));
+
+    let result = plugins
+        .transform_text(&plugin, "{{ #bold theme=dark\na\n}}", &get_test_context())
+        .unwrap();
+
+    assert!(result.contains("theme: \"dark\""));
+
+    let result = plugins.transform_text(
+        &plugin,
+        "{{ #bold theme=\"\\\"});alert(1);//\"\na\n}}",
+        &get_test_context(),
+    );
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "plugins/bold.html.lua: \"\"});alert(1);//\" isn't a valid Mermaid theme (in \"Chapter\")"
+    );
+}
+
+#[test]
+fn pass_context_to_plugins() {
+    let plugins = Plugins::new(
+        lua_runtime::LuaRuntime::new(),
+        crate::parser::get_test_config(),
+    );
+    let plugin = get_test_plugin(
+        "function transform(text, args, context)
+          return text .. ' ' .. context.chapter .. ' ' .. context.path .. ' ' .. context.renderer
+        end",
+    );
+
+    let result = plugins
+        .transform_text(&plugin, "{{ #bold a }}", &get_test_context())
+        .unwrap();
+
+    assert_eq!(result, "a Chapter chapter.md html");
+}
+
+#[test]
+fn report_lua_errors() {
+    let plugins = Plugins::new(
+        lua_runtime::LuaRuntime::new(),
+        crate::parser::get_test_config(),
+    );
+    let plugin = get_test_plugin("function transform(text)\n  return text .. nil\nend");
+
+    let result = plugins.transform_text(&plugin, "{{ #bold a }}", &get_test_context());
+
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "plugins/bold.html.lua:2: attempt to concatenate a nil value (in \"Chapter\")"
+    );
+}
+
+#[test]
+fn handle_custom_keywords_with_lua() {
+    let plugins = get_test_plugins(
+        "keywords = {
+              Jira = function(args) return '[' .. args .. '](https://jira/' .. args .. ')' end,
+              Endpoint = function(args, article)
+                return { content = article.topic .. ': ' .. args, metadata = { endpoint = args } }
+              end,
+            }",
+    );
+    let mut article = parser::get_test_article("Users", "", "src/users.rs");
+
+    assert_eq!(plugins.keywords().unwrap(), vec!["Endpoint", "Jira"]);
+
+    plugins
+        .handle_keyword("Jira", "ABC-123", &mut article)
+        .unwrap();
+    plugins
+        .handle_keyword("Endpoint", "GET /users", &mut article)
+        .unwrap();
+
+    assert_eq!(
+        article.content,
+        "[ABC-123](https://jira/ABC-123)\nUsers: GET /users\n"
+    );
+    assert_eq!(article.metadata["endpoint"], "GET /users");
+}
+
+#[test]
+fn run_lua_hooks() {
+    let plugins = get_test_plugins(
+        "function on_article(article)
+              if article.metadata.draft then return false end
+              return { topic = string.upper(article.topic), metadata = { reviewed = 'yes' } }
+            end
+
+            function on_finish(files)
+              error('cannot finish')
+            end",
+    );
+    let article = parser::get_test_article("Users", "text", "src/users.rs");
+    let mut draft = article.clone();
+    draft
+        .metadata
+        .insert(String::from("draft"), String::from("true"));
+
+    let article = plugins.on_article(article).unwrap().unwrap();
+
+    assert_eq!(article.topic, "USERS");
+    assert_eq!(article.content, "text");
+    assert_eq!(article.path, "src/users.rs");
+    assert_eq!(article.metadata["reviewed"], "yes");
+    assert!(plugins.on_article(draft).unwrap().is_none());
+    assert_eq!(
+        plugins
+            .run_hook("on_document", serde_json::json!({ "title": "A" }))
+            .unwrap(),
+        Some(serde_json::json!({ "title": "A" }))
+    );
+    assert_eq!(
+        plugins
+            .run_hook("on_finish", serde_json::json!([]))
+            .unwrap_err()
+            .to_string(),
+        "plugins/bold.html.lua:7: cannot finish (in on_finish)"
+    );
+}
+
+#[test]
+fn report_broken_lua_plugins() {
+    let plugins = get_test_plugins("keywords = {}\nfunction on_finish(");
+
+    assert!(plugins
+        .keywords()
+        .unwrap_err()
+        .starts_with("plugins/bold.html.lua:2: "));
+    assert!(plugins
+        .run_hook("on_finish", serde_json::json!([]))
+        .unwrap_err()
+        .to_string()
+        .starts_with("plugins/bold.html.lua:2: "));
+}
+
+#[test]
+fn process_books_without_plugins_dir() {
+    let mut config = crate::parser::get_test_config();
+    config.plugins_dir = None;
+    let mut plugins = Plugins::new(lua_runtime::LuaRuntime::new(), config);
+    let input = r#"[
+      {
+        "root": ".",
+        "config": { "book": { "title": "Book" } },
+        "renderer": "markdown",
+        "mdbook_version": "0.4.28"
+      },
+      {
+        "sections": [
+          {
+            "Chapter": {
+              "name": "Chapter",
+              "content": "{{ #mermaid\ngraph TD; A-->B\n}}",
+              "number": null,
+              "sub_items": [],
+              "path": "chapter.md",
+              "source_path": "chapter.md",
+              "parent_names": []
+            }
+          }
+        ],
+        "__non_exhaustive": null
+      }
+    ]"#;
+    let mut output = vec![];
+
+    plugins
+        .process_book(input.as_bytes(), &mut output, None)
+        .unwrap();
+
+    let book: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    assert_eq!(
+        book["sections"][0]["Chapter"]["content"],
+        "```mermaid\ngraph TD; A-->B\n```"
+    );
+
+    let mut output = vec![];
+
+    assert!(plugins
+        .process_book("not a book".as_bytes(), &mut output, None)
+        .unwrap_err()
+        .to_string()
+        .starts_with("Cannot read the book from mdBook: "));
+}
@@ -0,0 +1,558 @@
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+const REGION_START: &str = "fundoc-region:";
+const REGION_END: &str = "fundoc-endregion";
+const SOURCE_LINK_SCHEME: &str = "fundoc-source://";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snippet {
+    pub code: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Include {
+    pub path: String,
+    pub selector: Option<String>,
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Embed {
+    pub path: Option<String>,
+    pub kind: String,
+    pub name: String,
+    pub lang: Option<String>,
+}
+
+const INDENTATION_LANGUAGES: [&str; 10] = [
+    "py", "pyw", "rb", "lua", "ex", "exs", "jl", "nim", "coffee", "cr",
+];
+
+pub fn parse_include(args: &str) -> Result<Include, String> {
+    let mut words = args.split_whitespace();
+    let target = words
+        .next()
+        .ok_or_else(|| String::from("a path to a file is required"))?;
+    let (path, selector) = match target.split_once('#') {
+        Some((path, selector)) => (path, Some(selector.to_string())),
+        None => (target, None),
+    };
+
+    Ok(Include {
+        path: path.to_string(),
+        selector,
+        lang: words.next().map(String::from),
+    })
+}
+
+fn is_path(word: &str) -> bool {
+    word.contains('/') || word.contains('.')
+}
+
+pub fn parse_embed(args: &str) -> Result<Embed, String> {
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let path = match words.first() {
+        Some(word) if is_path(word) => Some(words.remove(0).to_string()),
+        _ => None,
+    };
+
+    match words.as_slice() {
+        [kind, name] | [kind, name, _] => Ok(Embed {
+            path,
+            kind: kind.to_string(),
+            name: name.to_string(),
+            lang: words.get(2).map(|lang| lang.to_string()),
+        }),
+        _ => Err(String::from(
+            "a kind and a name of a symbol are required, for example `fn calculate_total`",
+        )),
+    }
+}
+
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_line_range(selector: &str) -> Option<(usize, usize)> {
+    let re = Regex::new(r"^L?(\d+)(?:-L?(\d+))?$").unwrap();
+    let captures = re.captures(selector)?;
+    let start: usize = captures[1].parse().ok()?;
+    let end: usize = match captures.get(2) {
+        Some(end) => end.as_str().parse().ok()?,
+        None => start,
+    };
+
+    Some((start, end))
+}
+
+fn get_lines(lines: &[&str], start: usize, end: usize) -> Result<Snippet, String> {
+    if start == 0 || start > end || end > lines.len() {
+        return Err(format!(
+            "lines {}-{} are out of the file with {} lines",
+            start,
+            end,
+            lines.len()
+        ));
+    }
+
+    Ok(Snippet {
+        code: dedent(&lines[start - 1..end]),
+        start_line: start,
+        end_line: end,
+    })
+}
+
+fn get_region_name(line: &str) -> Option<&str> {
+    let start = line.find(REGION_START)? + REGION_START.len();
+
+    line[start..].split_whitespace().next()
+}
+
+fn find_region(lines: &[&str], name: &str) -> Result<Snippet, String> {
+    let start = lines
+        .iter()
+        .position(|line| get_region_name(line) == Some(name))
+        .ok_or_else(|| format!("cannot find the \"{}\" region", name))?;
+    let mut depth = 0;
+    let mut code: Vec<&str> = vec![];
+
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        if line.contains(REGION_START) {
+            depth += 1;
+        } else if line.contains(REGION_END) && depth > 0 {
+            depth -= 1;
+        } else if line.contains(REGION_END) {
+            return Ok(Snippet {
+                code: dedent(&code),
+                start_line: start + 2,
+                end_line: index,
+            });
+        } else {
+            code.push(line);
+        }
+    }
+
+    Err(format!("the \"{}\" region isn't closed", name))
+}
+
+/**
+ * @Article Syntax
+ *
+ * A region is marked by comments with `fundoc-region: <name>` and `fundoc-endregion` in any
+ * language:
+ *
+ * ```rust
+ * // fundoc-region: create-user
+ * let user = User::new("admin");
+ * // fundoc-endregion
+ * ```
+ *
+ * Regions can be nested, markers of nested regions are removed from the included code. Instead
+ * of a region, a line range can be included: `@Include src/main.rs#L10-L20`. Without a region or
+ * a range the whole file is included.
+ */
+pub fn read_snippet(path: &Path, selector: Option<&str>) -> Result<Snippet, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read \"{}\": {}", path.display(), e))?;
+    let lines: Vec<&str> = content.lines().collect();
+
+    match selector {
+        None => get_lines(&lines, 1, lines.len()),
+        Some(selector) => match parse_line_range(selector) {
+            Some((start, end)) => get_lines(&lines, start, end),
+            None => find_region(&lines, selector),
+        },
+    }
+}
+
+fn get_indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn find_indented_block_end(lines: &[&str], start: usize) -> usize {
+    let indent = get_indent(lines[start]);
+    let mut end = start;
+
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if get_indent(line) > indent {
+            end = index;
+        } else {
+            // languages like Ruby and Lua close blocks with `end` on the same level
+            if line.trim() == "end" {
+                end = index;
+            }
+            break;
+        }
+    }
+
+    end
+}
+
+fn find_braced_block_end(lines: &[&str], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    // `;` inside brackets and parentheses (`[u8; 32]`) doesn't end a declaration
+    let mut nesting = 0;
+    let mut is_opened = false;
+    let mut in_block_comment = false;
+
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        let chars: Vec<char> = line.chars().collect();
+        let mut position = 0;
+        let mut quote: Option<char> = None;
+
+        while position < chars.len() {
+            let c = chars[position];
+            let next = chars.get(position + 1).copied();
+
+            if in_block_comment {
+                if c == '*' && next == Some('/') {
+                    in_block_comment = false;
+                    position += 1;
+                }
+            } else if let Some(quote_char) = quote {
+                if c == '\\' {
+                    position += 1;
+                } else if c == quote_char {
+                    quote = None;
+                }
+            } else if c == '/' && next == Some('/') {
+                break;
+            } else if c == '/' && next == Some('*') {
+                in_block_comment = true;
+                position += 1;
+            } else if c == '"' || c == '`' {
+                quote = Some(c);
+            } else if c == '\'' && chars.get(position + 2) == Some(&'\'') {
+                position += 2;
+            } else if c == '\'' && next == Some('\\') {
+                quote = Some(c);
+            } else if c == '{' {
+                depth += 1;
+                is_opened = true;
+            } else if c == '}' {
+                depth -= 1;
+
+                if is_opened && depth == 0 {
+                    return Some(index);
+                }
+            } else if c == '[' || c == '(' {
+                nesting += 1;
+            } else if c == ']' || c == ')' {
+                nesting -= 1;
+            } else if c == ';' && !is_opened && nesting == 0 {
+                return Some(index);
+            }
+
+            position += 1;
+        }
+    }
+
+    None
+}
+
+// only modifiers like `pub` or `extern "C"` can precede a kind, so mentions in comments are skipped
+fn find_declaration(lines: &[&str], kind: &str, name: &str) -> Option<usize> {
+    let re = Regex::new(&format!(
+        r#"^\s*(([\w()]+|"[^"]*")\s+)*{}\s+{}([^\w]|$)"#,
+        regex::escape(kind),
+        regex::escape(name)
+    ))
+    .unwrap();
+
+    lines.iter().position(|line| re.is_match(line))
+}
+
+/**
+ * @Article Syntax
+ *
+ * A symbol is found by its kind and name, and its body is detected by matching braces. For
+ * Python, Ruby, Lua and other languages without braces the body is detected by indentation.
+ * Attributes and decorators right above the symbol (`#[derive(Debug)]`, `@property`) are embedded
+ * as well.
+ */
+pub fn find_symbol(path: &Path, kind: &str, name: &str) -> Result<Snippet, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read \"{}\": {}", path.display(), e))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let declaration = find_declaration(&lines, kind, name)
+        .ok_or_else(|| format!("cannot find `{} {}` in \"{}\"", kind, name, path.display()))?;
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let end = if INDENTATION_LANGUAGES.contains(&extension.as_str()) {
+        find_indented_block_end(&lines, declaration)
+    } else {
+        find_braced_block_end(&lines, declaration)
+            .ok_or_else(|| format!("the body of `{} {}` isn't closed", kind, name))?
+    };
+
+    let mut start = declaration;
+    while start > 0 {
+        let line = lines[start - 1].trim_start();
+
+        if line.starts_with("#[") || line.starts_with('@') {
+            start -= 1;
+        } else {
+            break;
+        }
+    }
+
+    get_lines(&lines, start + 1, end + 1)
+}
+
+pub fn format_snippet(snippet: &Snippet, lang: &str, path: &str) -> String {
+    format!(
+        "```{}\n{}\n```\n[[~]]({}{}-{}/{})\n",
+        lang, snippet.code, SOURCE_LINK_SCHEME, snippet.start_line, snippet.end_line, path
+    )
+}
+
+// links to included files can be created only after all paths are known
+pub fn resolve_source_links(
+    content: &str,
+    create_link: impl Fn(&str, usize, usize) -> Option<String>,
+) -> String {
+    let re = Regex::new(&format!(
+        r"\[\[~\]\]\({}(\d+)-(\d+)/([^)\n]*)\)\n?",
+        regex::escape(SOURCE_LINK_SCHEME)
+    ))
+    .unwrap();
+
+    re.replace_all(content, |captures: &regex::Captures| {
+        let start = captures[1].parse().unwrap_or(1);
+        let end = captures[2].parse().unwrap_or(start);
+
+        match create_link(&captures[3], start, end) {
+            Some(link) => format!("[[~]]({})\n", link),
+            None => String::from(""),
+        }
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+const TEST_FILE: &str = "fn main() {
+    // fundoc-region: setup
+    let config = read_config();
+    // fundoc-region: user
+    let user = User::new();
+    // fundoc-endregion
+    // fundoc-endregion
+}
+";
+
+#[cfg(test)]
+fn get_test_file(name: &str) -> std::path::PathBuf {
+    let file = std::env::temp_dir().join(format!("fundoc_snippets_{}.rs", name));
+    fs::write(&file, TEST_FILE).unwrap();
+
+    file
+}
+
+#[test]
+fn parse_include_arguments() {
+    assert_eq!(
+        parse_include("src/main.rs#setup rust"),
+        Ok(Include {
+            path: String::from("src/main.rs"),
+            selector: Some(String::from("setup")),
+            lang: Some(String::from("rust")),
+        })
+    );
+    assert_eq!(
+        parse_include("src/main.rs"),
+        Ok(Include {
+            path: String::from("src/main.rs"),
+            selector: None,
+            lang: None,
+        })
+    );
+    assert!(parse_include("").is_err());
+}
+
+#[test]
+fn read_regions_and_line_ranges() {
+    let file = get_test_file("regions");
+
+    assert_eq!(
+        read_snippet(&file, Some("setup")),
+        Ok(Snippet {
+            code: String::from("let config = read_config();\nlet user = User::new();"),
+            start_line: 3,
+            end_line: 6,
+        })
+    );
+    assert_eq!(
+        read_snippet(&file, Some("user")),
+        Ok(Snippet {
+            code: String::from("let user = User::new();"),
+            start_line: 5,
+            end_line: 5,
+        })
+    );
+    assert_eq!(
+        read_snippet(&file, Some("L1-L2")),
+        Ok(Snippet {
+            code: String::from("fn main() {\n    // fundoc-region: setup"),
+            start_line: 1,
+            end_line: 2,
+        })
+    );
+    assert_eq!(
+        read_snippet(&file, Some("missing")),
+        Err(String::from("cannot find the \"missing\" region"))
+    );
+    assert!(read_snippet(&file, Some("5-100")).is_err());
+
+    fs::remove_file(file).ok();
+}
+
+#[test]
+fn parse_embed_arguments() {
+    assert_eq!(
+        parse_embed("fn calculate_total"),
+        Ok(Embed {
+            path: None,
+            kind: String::from("fn"),
+            name: String::from("calculate_total"),
+            lang: None,
+        })
+    );
+    assert_eq!(
+        parse_embed("../models/invoice.rs struct Invoice rust"),
+        Ok(Embed {
+            path: Some(String::from("../models/invoice.rs")),
+            kind: String::from("struct"),
+            name: String::from("Invoice"),
+            lang: Some(String::from("rust")),
+        })
+    );
+    assert!(parse_embed("calculate_total").is_err());
+}
+
+#[test]
+fn find_symbols_by_braces_and_indentation() {
+    let rust_file = std::env::temp_dir().join("fundoc_snippets_symbols.rs");
+    fs::write(
+        &rust_file,
+        "struct Unit;\n\n#[derive(Debug)]\npub struct Invoice {\n    total: u32,\n}\n\nfn calculate_total_tax() {}\n\nfn calculate_total(items: &[u32]) -> u32 {\n    // }\n    let brace = '}';\n    let text = \"{\";\n    items.iter().sum()\n}\n",
+    )
+    .unwrap();
+    let python_file = std::env::temp_dir().join("fundoc_snippets_symbols.py");
+    fs::write(
+        &python_file,
+        "class Invoice:\n    @property\n    def total(self):\n        return 1\n\n    def tax(self):\n        return 0\n\nprint(1)\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        find_symbol(&rust_file, "struct", "Unit"),
+        Ok(Snippet {
+            code: String::from("struct Unit;"),
+            start_line: 1,
+            end_line: 1,
+        })
+    );
+    assert_eq!(
+        find_symbol(&rust_file, "struct", "Invoice"),
+        Ok(Snippet {
+            code: String::from("#[derive(Debug)]\npub struct Invoice {\n    total: u32,\n}"),
+            start_line: 3,
+            end_line: 6,
+        })
+    );
+    assert_eq!(
+        find_symbol(&rust_file, "fn", "calculate_total")
+            .map(|snippet| (snippet.start_line, snippet.end_line)),
+        Ok((10, 15))
+    );
+    assert_eq!(
+        find_symbol(&python_file, "def", "total"),
+        Ok(Snippet {
+            code: String::from("@property\ndef total(self):\n    return 1"),
+            start_line: 2,
+            end_line: 4,
+        })
+    );
+    assert_eq!(
+        find_symbol(&python_file, "class", "Invoice").map(|snippet| snippet.end_line),
+        Ok(7)
+    );
+    assert!(find_symbol(&rust_file, "fn", "missing").is_err());
+
+    fs::remove_file(rust_file).ok();
+    fs::remove_file(python_file).ok();
+}
+
+#[test]
+fn find_symbols_with_brackets_and_modifiers() {
+    let rust_file = std::env::temp_dir().join("fundoc_snippets_signatures.rs");
+    fs::write(
+        &rust_file,
+        "const TABLE: [u8; 4] = [\n    1, 2, 3, 4,\n];\n\nfn hash(data: [u8; 32]) -> Digest {\n    digest(data)\n}\n\npub unsafe extern \"C\" fn fundoc_free(ptr: *mut u8) {\n    drop(ptr);\n}\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        find_symbol(&rust_file, "const", "TABLE")
+            .map(|snippet| (snippet.start_line, snippet.end_line)),
+        Ok((1, 3))
+    );
+    assert_eq!(
+        find_symbol(&rust_file, "fn", "hash").map(|snippet| (snippet.start_line, snippet.end_line)),
+        Ok((5, 7))
+    );
+    assert_eq!(
+        find_symbol(&rust_file, "fn", "fundoc_free")
+            .map(|snippet| (snippet.start_line, snippet.end_line)),
+        Ok((9, 11))
+    );
+
+    fs::remove_file(rust_file).ok();
+}
+
+#[test]
+fn resolve_links_to_included_files() {
+    let snippet = Snippet {
+        code: String::from("let a = 1;"),
+        start_line: 3,
+        end_line: 4,
+    };
+    let content = format!(
+        "Text\n{}Text",
+        format_snippet(&snippet, "rust", "./src/a.rs")
+    );
+
+    assert_eq!(
+        resolve_source_links(&content, |path, start, end| Some(format!(
+            "{}#L{}-L{}",
+            path, start, end
+        ))),
+        "Text\n```rust\nlet a = 1;\n```\n[[~]](./src/a.rs#L3-L4)\nText"
+    );
+    assert_eq!(
+        resolve_source_links(&content, |_, _, _| None),
+        "Text\n```rust\nlet a = 1;\n```\nText"
+    );
+}
@@ -0,0 +1,260 @@
+use std::fs;
+use std::num::NonZeroUsize;
+
+use crate::config;
+use crate::git;
+use crate::parser;
+
+const DEFAULT_CODE_LINES: usize = 20;
+const SECONDS_IN_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug)]
+pub struct StaleSection {
+    pub topic: String,
+    pub path: String,
+    pub line: i16,
+    pub comment_change: git::Commit,
+    pub code_change: git::Commit,
+}
+
+// code of a code block article is a part of the article, otherwise the code follows the comment
+// until the next comment block
+fn get_code_range(
+    article: &parser::Article,
+    lines: &[&str],
+    comment_start: &str,
+    code_lines: usize,
+) -> Option<(i16, i16)> {
+    let start_line = article.code_line?;
+
+    if start_line <= article.end_line {
+        return Some((start_line, article.end_line));
+    }
+
+    let start = start_line as usize;
+    let last = start.saturating_add(code_lines - 1).min(lines.len());
+    let end_line = (start..=last)
+        .take_while(|line| !lines[line - 1].trim_start().starts_with(comment_start))
+        .last()?;
+
+    Some((start_line, end_line as i16))
+}
+
+fn get_comment_range(article: &parser::Article) -> (i16, i16) {
+    match article.code_line {
+        Some(code_line) if code_line <= article.end_line => (article.start_line, code_line - 1),
+        _ => (article.start_line, article.end_line),
+    }
+}
+
+fn is_stale(comment_change: &git::Commit, code_change: &git::Commit, threshold_days: i64) -> bool {
+    code_change.timestamp - comment_change.timestamp > threshold_days * SECONDS_IN_DAY
+}
+
+/**
+ * @Article Stale documentation
+ *
+ * `fundoc stale` looks for documentation sections which may be out of date. A section is marked as
+ * stale when the code right after its comment block was changed in a commit newer than the last
+ * change of the comment itself. The code ends before the next comment block, so changes of other
+ * sections don't make a section stale. For sections with `@CodeBlockStart` the code of the block
+ * is checked.
+ *
+ * It can be configured by the following parameters:
+ *
+ * - `stale_code_lines` - how many lines after a comment block are considered as the code it
+ *   describes. By default, it's `20`.
+ * - `stale_threshold_days` - how many days the code can be newer than the comment before the
+ *   section is marked as stale. By default, it's `0`.
+ */
+pub fn find_stale_sections(
+    articles: &[parser::Article],
+    root: &str,
+    config: &config::Config,
+) -> Vec<StaleSection> {
+    let code_lines = config
+        .stale_code_lines
+        .map_or(DEFAULT_CODE_LINES, NonZeroUsize::get);
+    let comment_start = config.comment_start_string.as_deref().unwrap_or("/**");
+    let threshold_days = config.stale_threshold_days.unwrap_or(0);
+    let mut sections: Vec<StaleSection> = vec![];
+
+    for article in articles {
+        if article.path.ends_with(".fdoc.md") {
+            continue;
+        }
+
+        let Ok(content) = fs::read_to_string(format!("{}/{}", root, article.path)) else {
+            continue;
+        };
+        let lines: Vec<&str> = content.lines().collect();
+
+        let Some((code_start, code_end)) =
+            get_code_range(article, &lines, comment_start, code_lines)
+        else {
+            continue;
+        };
+        let (comment_start, comment_end) = get_comment_range(article);
+
+        let comment_change = git::last_commit(root, &article.path, comment_start, comment_end);
+        let code_change = git::last_commit(root, &article.path, code_start, code_end);
+
+        if let (Some(comment_change), Some(code_change)) = (comment_change, code_change) {
+            if is_stale(&comment_change, &code_change, threshold_days) {
+                sections.push(StaleSection {
+                    topic: article.topic.clone(),
+                    path: article.path.clone(),
+                    line: article.start_line,
+                    comment_change,
+                    code_change,
+                });
+            }
+        }
+    }
+
+    sections
+}
+
+fn short_sha(commit: &git::Commit) -> &str {
+    &commit.sha[..commit.sha.len().min(7)]
+}
+
+pub fn create_report(sections: &[StaleSection]) -> String {
+    if sections.is_empty() {
+        return String::from("No stale documentation sections found\n");
+    }
+
+    let mut sections: Vec<&StaleSection> = sections.iter().collect();
+    sections.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+
+    let mut report = format!("Stale documentation sections: {}\n\n", sections.len());
+
+    for section in sections {
+        report += &format!(
+            "{}:{} \"{}\"\n  the comment was changed on {} ({}), the code was changed on {} ({}) by {}\n",
+            section.path,
+            section.line,
+            section.topic,
+            section.comment_change.date,
+            short_sha(&section.comment_change),
+            section.code_change.date,
+            short_sha(&section.code_change),
+            section.code_change.author
+        );
+    }
+
+    report
+}
+
+#[cfg(test)]
+fn create_commit(sha: &str, date: &str, timestamp: i64) -> git::Commit {
+    git::Commit {
+        sha: String::from(sha),
+        author: String::from("Author"),
+        date: String::from(date),
+        timestamp,
+    }
+}
+
+#[cfg(test)]
+fn parse_test_article(content: &str, comments: Option<(&str, char, &str)>) -> parser::Article {
+    let mut config = parser::get_test_config();
+
+    if let Some((start, prefix, end)) = comments {
+        config.comment_start_string = Some(start.to_string());
+        config.comment_prefix = Some(prefix);
+        config.comment_end_string = Some(end.to_string());
+    }
+
+    let mut parser = parser::Parser::new(config);
+    let articles = parser.parse_file(content, "src/main.rs");
+
+    articles[0].clone()
+}
+
+#[cfg(test)]
+fn get_test_lines(content: &str, code_lines: usize) -> Vec<String> {
+    format!("{}{}", content, "let a = 1;\n".repeat(code_lines))
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn get_code_range_after_comment() {
+    let content = "/**\n * @Article Intro\n * Text\n */\n";
+    let article = parse_test_article(content, None);
+    let lines = get_test_lines(content, 95);
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+    assert_eq!(get_comment_range(&article), (2, 3));
+    assert_eq!(get_code_range(&article, &lines, "/**", 20), Some((5, 24)));
+    assert_eq!(
+        get_code_range(&article, &lines[..10], "/**", 20),
+        Some((5, 10))
+    );
+    assert_eq!(get_code_range(&article, &lines[..4], "/**", 20), None);
+    assert_eq!(
+        get_code_range(&article, &lines, "/**", usize::MAX),
+        Some((5, 99))
+    );
+}
+
+#[test]
+fn get_code_range_until_next_comment() {
+    let content =
+        "/**\n * @Article Intro\n * Text\n */\nfn main() {}\n\n  /**\n   * @Article Usage\n   */\nfn run() {}\n";
+    let article = parse_test_article(content, None);
+    let lines: Vec<&str> = content.lines().collect();
+
+    assert_eq!(get_code_range(&article, &lines, "/**", 20), Some((5, 6)));
+}
+
+#[test]
+fn get_code_range_after_line_comments() {
+    let content = "//>\n// @Article Intro\n// Text\n//<\n";
+    let article = parse_test_article(content, Some(("//>", '/', "//<")));
+    let lines = get_test_lines(content, 95);
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+    assert_eq!(get_comment_range(&article), (2, 3));
+    assert_eq!(get_code_range(&article, &lines, "//>", 20), Some((5, 24)));
+}
+
+#[test]
+fn get_code_range_of_code_blocks() {
+    let article = parse_test_article(
+        "/**\n * @Article Usage\n * @CodeBlockStart rust\n */\ncalc_size(item);\nprint(item);\n/**\n * @CodeBlockEnd\n */\nfn main() {}\n",
+        None,
+    );
+
+    assert_eq!(get_comment_range(&article), (2, 4));
+    assert_eq!(get_code_range(&article, &[], "/**", 20), Some((5, 6)));
+}
+
+#[test]
+fn check_stale_threshold() {
+    let comment_change = create_commit("a", "2023-04-01", 1680307200);
+    let code_change = create_commit("b", "2023-04-06", 1680739200);
+
+    assert!(is_stale(&comment_change, &code_change, 0));
+    assert!(is_stale(&comment_change, &code_change, 4));
+    assert!(!is_stale(&comment_change, &code_change, 5));
+    assert!(!is_stale(&code_change, &comment_change, 0));
+}
+
+#[test]
+fn create_stale_sections_report() {
+    let sections = vec![StaleSection {
+        topic: String::from("Intro"),
+        path: String::from("src/main.rs"),
+        line: 3,
+        comment_change: create_commit("1111111111", "2023-04-01", 1680307200),
+        code_change: create_commit("2222222222", "2023-04-06", 1680739200),
+    }];
+
+    assert_eq!(
+        create_report(&sections),
+        "Stale documentation sections: 1\n\nsrc/main.rs:3 \"Intro\"\n  the comment was changed on 2023-04-01 (1111111), the code was changed on 2023-04-06 (2222222) by Author\n"
+    );
+}
@@ -0,0 +1,210 @@
+use std::fs::File;
+use wasmi::core::{Trap, TrapCode};
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/**
+ * @Article Plugins
+ *
+ * ## WebAssembly plugins
+ *
+ * Plugins can be compiled to WebAssembly from any language which supports it. Put
+ * `your-plugin-name.wasm` into the plugins folder, and it will transform
+ * `{{ #your-plugin-name ... }}` blocks. WebAssembly plugins run in a sandbox without access to the
+ * file system, the network or any other API of the host, so it's safe to use plugins from cloned
+ * repositories.
+ *
+ * A module should export its `memory` and two functions:
+ *
+ * - `alloc(len: i32) -> i32` - allocates `len` bytes for the input and returns a pointer
+ * - `transform(ptr: i32, len: i32) -> i64` - transforms the UTF-8 text of a block and returns a
+ *   pointer to the result in the high 32 bits and its length in the low 32 bits
+ *
+ * A plugin can fail with a trap (for example, `unreachable`). Execution of a plugin is limited by
+ * fuel which is set by `plugins_instruction_limit`, and its memory is limited by
+ * `plugins_memory_limit_mb`.
+ */
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Result<Module, String>,
+    fuel: u64,
+    memory_limit: usize,
+}
+
+fn describe_trap(trap: Trap, fuel: u64) -> String {
+    match trap.trap_code() {
+        Some(TrapCode::OutOfFuel) => format!("the plugin exceeded the fuel limit of {}", fuel),
+        _ => trap.to_string(),
+    }
+}
+
+impl WasmPlugin {
+    pub fn load(file: &str, fuel: u64, memory_limit: usize) -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = File::open(file)
+            .map_err(|e| e.to_string())
+            .and_then(|wasm| Module::new(&engine, wasm).map_err(|e| e.to_string()));
+
+        Self {
+            engine,
+            module,
+            fuel,
+            memory_limit,
+        }
+    }
+
+    pub fn transform(&self, text: &str) -> Result<String, String> {
+        let module = self.module.as_ref().map_err(String::clone)?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.memory_limit)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.add_fuel(self.fuel).map_err(|e| e.to_string())?;
+
+        let error = |err: wasmi::Error| err.to_string();
+        let trap = |trap: Trap| describe_trap(trap, self.fuel);
+        let instance = Linker::<StoreLimits>::new(&self.engine)
+            .instantiate(&mut store, module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(error)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| String::from("the plugin doesn't export `memory`"))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(error)?;
+        let transform = instance
+            .get_typed_func::<(i32, i32), i64>(&store, "transform")
+            .map_err(error)?;
+
+        let input = text.as_bytes();
+        let input_ptr = alloc.call(&mut store, input.len() as i32).map_err(trap)?;
+        memory
+            .write(&mut store, input_ptr as u32 as usize, input)
+            .map_err(|e| e.to_string())?;
+
+        let output = transform
+            .call(&mut store, (input_ptr, input.len() as i32))
+            .map_err(trap)?;
+        let output_ptr = (output >> 32) as u32 as usize;
+        let output_len = output as u32 as usize;
+
+        // the length comes from the plugin, so it's checked before allocating a buffer
+        if output_ptr
+            .checked_add(output_len)
+            .is_none_or(|end| end > memory.data(&store).len())
+        {
+            return Err(format!(
+                "the plugin returned {} bytes at {} which are outside of its memory",
+                output_len, output_ptr
+            ));
+        }
+
+        let mut buffer = vec![0; output_len];
+        memory
+            .read(&store, output_ptr, &mut buffer)
+            .map_err(|e| e.to_string())?;
+
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+fn get_test_plugin(name: &str, wat: &str, fuel: u64) -> WasmPlugin {
+    let file = std::env::temp_dir().join(format!("fundoc_{}.wasm", name));
+    std::fs::write(&file, wat::parse_str(wat).unwrap()).unwrap();
+
+    let plugin = WasmPlugin::load(&file.to_string_lossy(), fuel, 1024 * 1024);
+    std::fs::remove_file(file).ok();
+
+    plugin
+}
+
+#[cfg(test)]
+const UPPERCASE_PLUGIN: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32)
+    i32.const 1024)
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (local $i i32)
+    (local $c i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+        (if (i32.and (i32.ge_u (local.get $c) (i32.const 97)) (i32.le_u (local.get $c) (i32.const 122)))
+          (then (local.set $c (i32.sub (local.get $c) (i32.const 32)))))
+        (i32.store8 (i32.add (local.get $ptr) (local.get $i)) (local.get $c))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len)))))
+"#;
+
+#[test]
+fn transform_text_with_wasm_plugin() {
+    let plugin = get_test_plugin("uppercase", UPPERCASE_PLUGIN, 100_000);
+
+    assert_eq!(plugin.transform("some text"), Ok(String::from("SOME TEXT")));
+}
+
+#[test]
+fn limit_wasm_plugin_fuel() {
+    let plugin = get_test_plugin(
+        "endless",
+        r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) i32.const 0)
+          (func (export "transform") (param i32 i32) (result i64)
+            (loop $endless (br $endless))
+            i64.const 0))
+        "#,
+        10_000,
+    );
+
+    assert_eq!(
+        plugin.transform("text"),
+        Err(String::from("the plugin exceeded the fuel limit of 10000"))
+    );
+}
+
+#[test]
+fn report_invalid_wasm_plugins() {
+    let plugin = get_test_plugin("invalid", "(module)", 10_000);
+
+    assert_eq!(
+        plugin.transform("text"),
+        Err(String::from("the plugin doesn't export `memory`"))
+    );
+    assert!(WasmPlugin::load("./plugins/missing.wasm", 10_000, 1024)
+        .transform("text")
+        .is_err());
+}
+
+#[test]
+fn report_results_outside_of_memory() {
+    let plugin = get_test_plugin(
+        "outside",
+        r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32)
+    i32.const 0)
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    i64.const 0xfffffff0))
+"#,
+        10_000,
+    );
+
+    assert_eq!(
+        plugin.transform("text"),
+        Err(String::from(
+            "the plugin returned 4294967280 bytes at 0 which are outside of its memory"
+        ))
+    );
+}